@@ -1,10 +1,14 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
-use egui::Color32;
+use egui::{Color32, FontId};
 use serde::{de::Visitor, Deserialize, Serialize};
 
-use crate::layout::Layout;
+use crate::{
+    layout::Layout,
+    module::{self, ModuleConfig, Slot},
+};
 mod backend;
+mod fonts;
 mod user_config;
 
 pub fn run(protocol: Protocol, config: PagbarConfig) {
@@ -22,16 +26,242 @@ pub enum Position {
     Bottom,
 }
 
+/// Which outputs a bar is placed on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorSelector {
+    /// Position in the list of available monitors
+    Index(usize),
+    /// RandR output name, e.g. `DP-1`
+    Name(String),
+    Primary,
+    All,
+}
+
+impl std::fmt::Display for MonitorSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MonitorSelector::Index(index) => write!(f, "{index}"),
+            MonitorSelector::Name(name) => write!(f, "\"{name}\""),
+            MonitorSelector::Primary => f.write_str("\"primary\""),
+            MonitorSelector::All => f.write_str("\"all\""),
+        }
+    }
+}
+
+impl Serialize for MonitorSelector {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            MonitorSelector::Index(index) => serializer.serialize_u64(*index as u64),
+            MonitorSelector::Name(name) => serializer.serialize_str(name),
+            MonitorSelector::Primary => serializer.serialize_str("primary"),
+            MonitorSelector::All => serializer.serialize_str("all"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MonitorSelector {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(MonitorSelectorVisitor)
+    }
+}
+
+struct MonitorSelectorVisitor;
+
+impl<'de> Visitor<'de> for MonitorSelectorVisitor {
+    type Value = MonitorSelector;
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        usize::try_from(v)
+            .map(MonitorSelector::Index)
+            .map_err(|_| E::custom(format!("invalid monitor index {}", v)))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        usize::try_from(v)
+            .map(MonitorSelector::Index)
+            .map_err(|_| E::custom(format!("invalid monitor index {}", v)))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        match v {
+            "" => Err(E::custom("empty monitor name")),
+            "primary" => Ok(MonitorSelector::Primary),
+            "all" => Ok(MonitorSelector::All),
+            name => Ok(MonitorSelector::Name(name.into())),
+        }
+    }
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("monitor index, output name, \"primary\" or \"all\"")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    Start,
+    #[default]
+    Center,
+    End,
+}
+
+/// Where a bar is stacked relative to other windows
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    /// Above normal windows
+    #[default]
+    Top,
+    /// Below normal windows
+    Bottom,
+    /// Above everything, including fullscreen windows
+    Overlay,
+}
+
+/// Extent of a bar along its edge, either in pixels or relative to the monitor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Pixels(u32),
+    Percent(f32),
+}
+
+impl Default for Length {
+    fn default() -> Self {
+        Length::Percent(100.)
+    }
+}
+
+impl Length {
+    /// Resolve against the available space, never exceeding it
+    pub fn resolve(self, available: u32) -> u32 {
+        match self {
+            Length::Pixels(px) => px.min(available),
+            Length::Percent(percent) => {
+                ((available as f32 * percent / 100.).round() as u32).min(available)
+            }
+        }
+    }
+}
+
+impl Serialize for Length {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Length::Pixels(px) => serializer.serialize_u32(*px),
+            Length::Percent(percent) => serializer.serialize_str(&format!("{percent}%")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Length {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(LengthVisitor)
+    }
+}
+
+struct LengthVisitor;
+
+impl<'de> Visitor<'de> for LengthVisitor {
+    type Value = Length;
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        u32::try_from(v)
+            .map(Length::Pixels)
+            .map_err(|_| E::custom(format!("invalid length {}", v)))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        u32::try_from(v)
+            .map(Length::Pixels)
+            .map_err(|_| E::custom(format!("invalid length {}", v)))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        if let Some(percent) = v.strip_suffix('%') {
+            if let Ok(percent) = percent.trim().parse::<f32>() {
+                if (0. ..=100.).contains(&percent) {
+                    return Ok(Length::Percent(percent));
+                }
+            }
+        } else if let Ok(px) = v.trim().trim_end_matches("px").parse::<u32>() {
+            return Ok(Length::Pixels(px));
+        }
+
+        Err(E::custom(format!("invalid length {}", v)))
+    }
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("pixel count or percentage string")
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    pub fn opaque(self) -> Self {
+        Self { a: 255, ..self }
+    }
+}
+
+/// `#RRGGBB` or `#RRGGBBAA`
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<Self, Self::Err> {
+        if v.starts_with('#') && (v.len() == 7 || v.len() == 9) {
+            let channel = |i: usize| u8::from_str_radix(v.get(i..i + 2).unwrap_or_default(), 16);
+            let a = if v.len() == 9 { channel(7) } else { Ok(255) };
+            if let (Ok(r), Ok(g), Ok(b), Ok(a)) = (channel(1), channel(3), channel(5), a) {
+                return Ok(Color { r, g, b, a });
+            }
+        }
+
+        Err(format!("invalid hex {}", v))
+    }
 }
 
 impl From<Color> for Color32 {
     fn from(value: Color) -> Self {
-        Color32::from_rgb(value.r, value.g, value.b)
+        Color32::from_rgba_unmultiplied(value.r, value.g, value.b, value.a)
     }
 }
 
@@ -40,7 +270,14 @@ impl Serialize for Color {
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(&format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b))
+        if self.a == 255 {
+            serializer.serialize_str(&format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b))
+        } else {
+            serializer.serialize_str(&format!(
+                "#{:02X}{:02X}{:02X}{:02X}",
+                self.r, self.g, self.b, self.a
+            ))
+        }
     }
 }
 
@@ -62,106 +299,279 @@ impl<'de> Visitor<'de> for ColorVisitor {
     where
         E: serde::de::Error,
     {
-        if v.starts_with('#') && v.len() >= 7 {
-            if let (Ok(r), Ok(g), Ok(b)) = (
-                u8::from_str_radix(&v[1..3], 16),
-                u8::from_str_radix(&v[3..5], 16),
-                u8::from_str_radix(&v[5..7], 16),
-            ) {
-                return Ok(Color { r, g, b });
-            }
-        }
-
-        Err(E::custom(format!("invalid hex {}", v)))
+        v.parse().map_err(E::custom)
     }
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("rgb hex string")
+        formatter.write_str("rgb or rgba hex string")
     }
 }
 
+#[derive(Clone)]
 pub struct Bar {
-    pub monitor: usize,
+    /// Key of its `[bar.<name>]` section
+    pub name: String,
+    pub monitor: MonitorSelector,
     pub title: String,
     pub position: Position,
     pub size: u16,
+    pub margin: u16,
+    pub length: Length,
+    pub align: Align,
+    pub corner_radius: f32,
+    pub transparent: bool,
     pub background: Color,
     pub text: Color,
     pub text_secondary: Color,
+    /// Values of modules past their `states` thresholds
+    pub warning: Color,
+    pub critical: Color,
+    pub fonts: Arc<egui::FontDefinitions>,
+    pub modules: Vec<ModuleConfig>,
+    /// Layout added by a plugin, the layout factory's when unset
+    pub layout: Option<String>,
+    pub autohide: Option<Autohide>,
+    pub layer: Layer,
+    /// Bypass the window manager, for ones without dock support
+    pub override_redirect: bool,
 }
 
-type PagbarConfig = Vec<(Bar, Box<dyn Layout>)>;
+/// Timing of a bar that collapses to its edge while not hovered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Autohide {
+    /// Seconds the bar stays after the pointer left it
+    pub delay: f32,
+    /// Seconds the bar takes to slide in or out
+    pub duration: f32,
+}
 
-pub fn from_path(path: PathBuf, layout_factory: fn() -> Box<dyn Layout>) -> PagbarConfig {
+pub type LayoutFactory = fn(Vec<Slot>) -> Box<dyn Layout>;
+
+/// `layout` value that picks the layout factory's layout
+const DEFAULT_LAYOUT: &str = "three-split";
+
+/// Bars with the layout each of their windows is built from,
+/// a bar spanning several monitors gets a layout per window
+type PagbarConfig = Vec<(Bar, LayoutFactory)>;
+
+pub fn from_path(path: PathBuf, layout_factory: LayoutFactory) -> PagbarConfig {
     let mut result = Vec::new();
     let config = toml::from_str::<user_config::UserConfig>(
         &std::fs::read_to_string(path.clone())
-            .expect(format!("Config file not found! {:?}", path).as_str()),
+            .unwrap_or_else(|_| panic!("Config file not found! {:?}", path)),
     )
     .unwrap();
 
-    for (_, bar) in config.bar {
+    let fonts = Arc::new(fonts::load(&config.fonts));
+    let modules = if config.module.is_empty() {
+        ModuleConfig::defaults()
+    } else {
+        config
+            .module
+            .iter()
+            .map(|(name, user_module)| module_config(&config.fonts, name, user_module))
+            .collect()
+    };
+
+    for (name, bar) in config.bar {
+        let autohide = bar.autohide.then(|| Autohide {
+            delay: bar.autohide_delay.unwrap_or(1.),
+            duration: bar.autohide_duration.unwrap_or(0.2),
+        });
+        if let Some(autohide) = autohide {
+            if !(autohide.delay >= 0. && autohide.duration >= 0.) {
+                panic!("[bar.{name}]: autohide-delay and autohide-duration can't be negative");
+            }
+        }
+
+        let layout = bar.layout.filter(|layout| layout != DEFAULT_LAYOUT);
+        if let Some(layout) = &layout {
+            #[cfg(feature = "native-plugins")]
+            let known = crate::plugin::has_layout(layout);
+            #[cfg(not(feature = "native-plugins"))]
+            let known = false;
+            if !known {
+                panic!("[bar.{name}]: unknown layout \"{layout}\", is its plugin installed?");
+            }
+        }
+
         result.push((
             Bar {
+                name: name.clone(),
                 monitor: bar.monitor,
                 title: config.title.clone().unwrap_or("pagbar".into()),
                 position: bar.position,
                 size: bar.size,
-                background: config
+                margin: bar.margin,
+                length: bar.length,
+                align: bar.align,
+                corner_radius: bar.corner_radius,
+                transparent: bar.transparent,
+                background: config.colors.background.unwrap_or(Color::rgb(0, 0, 0)),
+                text: config.colors.text.unwrap_or(Color::rgb(255, 255, 255)),
+                text_secondary: config
                     .colors
-                    .background
-                    .unwrap_or(Color { r: 0, g: 0, b: 0 }),
-                text: config.colors.text.unwrap_or(Color {
-                    r: 255,
-                    g: 255,
-                    b: 255,
-                }),
-                text_secondary: config.colors.text_secondary.unwrap_or(Color {
-                    r: 150,
-                    g: 150,
-                    b: 150,
-                }),
+                    .text_secondary
+                    .unwrap_or(Color::rgb(150, 150, 150)),
+                warning: config
+                    .colors
+                    .warning
+                    .unwrap_or(Color::rgb(0xe5, 0xc0, 0x7b)),
+                critical: config
+                    .colors
+                    .critical
+                    .unwrap_or(Color::rgb(0xe0, 0x6c, 0x75)),
+                fonts: fonts.clone(),
+                modules: modules.clone(),
+                layout,
+                autohide,
+                layer: bar.layer,
+                override_redirect: bar.override_redirect,
             },
-            layout_factory(),
+            layout_factory,
         ))
     }
     result
 }
+fn module_config(
+    fonts: &user_config::UserConfigFonts,
+    name: &str,
+    user_module: &user_config::UserConfigModule,
+) -> ModuleConfig {
+    let kind = user_module.kind.clone().unwrap_or(name.into());
+    let kinds = module::kinds();
+    if !kinds.contains(&kind) {
+        panic!(
+            "[module.{name}]: unknown kind \"{kind}\", expected one of {}",
+            kinds.join(", ")
+        );
+    }
+
+    let family = user_module.font_family.as_deref().unwrap_or("proportional");
+    if !fonts::is_family(fonts, family) {
+        panic!("[module.{name}]: font family \"{family}\" is not defined in [fonts.families]");
+    }
+
+    let config = ModuleConfig {
+        name: name.into(),
+        kind,
+        panel: user_module.panel,
+        font: FontId::new(user_module.font_size.unwrap_or(18.), fonts::family(family)),
+        actions: user_module.actions.clone(),
+        states: user_module.states,
+        color_warning: user_module.color_warning,
+        color_critical: user_module.color_critical,
+        blink: user_module.blink,
+        options: user_module.options.clone(),
+    };
+    // catch bad options now rather than when the bar is created
+    if let Err(err) = module::create(&config.kind, &config.options) {
+        panic!("[module.{name}]: {err}");
+    }
+    config
+}
+
+impl Bar {
+    /// Layout of a window of this bar, with a fresh set of slots
+    pub fn create_layout(&self, layout_factory: LayoutFactory) -> Box<dyn Layout> {
+        let slots = self.modules.iter().map(Slot::new).collect();
+        match &self.layout {
+            #[cfg(feature = "native-plugins")]
+            Some(layout) => crate::plugin::create_layout(layout, slots)
+                .unwrap_or_else(|| panic!("layout \"{layout}\" is not loaded")),
+            _ => layout_factory(slots),
+        }
+    }
+
+    /// Background the window surface is cleared with, only
+    /// translucent when the bar was created with an ARGB visual
+    pub fn clear_color(&self) -> egui::Rgba {
+        if self.transparent && !self.rounded() {
+            Color32::from(self.background).into()
+        } else {
+            egui::Rgba::default()
+        }
+    }
+
+    /// Background painted behind the panels of a rounded bar,
+    /// the surface itself is left clear around the corners
+    pub fn rounded_background(&self) -> Option<(f32, Color32)> {
+        if !self.rounded() {
+            return None;
+        }
+
+        let background = if self.transparent {
+            self.background
+        } else {
+            self.background.opaque()
+        };
+        Some((self.corner_radius, background.into()))
+    }
+
+    fn rounded(&self) -> bool {
+        self.corner_radius > 0.
+    }
+
+    /// Whether the window needs an ARGB visual, for translucency
+    /// or for clear corners around a rounded bar
+    pub fn alpha(&self) -> bool {
+        self.transparent || self.rounded()
+    }
+}
+
 impl From<&Bar> for egui::Visuals {
     fn from(value: &Bar) -> Self {
+        // transparent bars are painted by the surface clear color,
+        // filling panels as well would stack the alpha twice
+        let background = if value.transparent || value.rounded() {
+            Color32::TRANSPARENT
+        } else {
+            value.background.opaque().into()
+        };
+
         egui::Visuals {
             dark_mode: false,
-            extreme_bg_color: egui::Color32::from_rgb(
-                value.background.r,
-                value.background.g,
-                value.background.b,
-            ),
-            faint_bg_color: egui::Color32::from_rgb(
-                value.background.r,
-                value.background.g,
-                value.background.b,
-            ),
-            window_fill: egui::Color32::from_rgb(
-                value.background.r,
-                value.background.g,
-                value.background.b,
-            ),
-            panel_fill: egui::Color32::from_rgb(
-                value.background.r,
-                value.background.g,
-                value.background.b,
-            ),
-            override_text_color: Some(egui::Color32::from_rgb(
-                value.background.r,
-                value.background.g,
-                value.background.b,
-            )),
+            extreme_bg_color: background,
+            faint_bg_color: background,
+            window_fill: background,
+            panel_fill: background,
+            override_text_color: Some(value.background.opaque().into()),
 
             ..Default::default()
         }
     }
 }
 
+#[cfg(test)]
+impl Bar {
+    /// Bar without fonts or modules, for testing what modules draw
+    pub fn test() -> Self {
+        Self {
+            name: "test".into(),
+            monitor: MonitorSelector::Index(0),
+            title: "pagbar".into(),
+            position: Position::Top,
+            size: 30,
+            margin: 0,
+            length: Length::default(),
+            align: Align::default(),
+            corner_radius: 0.,
+            transparent: false,
+            background: Color::rgb(0x10, 0x10, 0x10),
+            text: Color::rgb(0xff, 0xff, 0xff),
+            text_secondary: Color::rgb(0x88, 0x88, 0x88),
+            warning: Color::rgb(0xff, 0xaa, 0x00),
+            critical: Color::rgb(0xff, 0x00, 0x00),
+            fonts: Arc::default(),
+            modules: Vec::new(),
+            layout: None,
+            autohide: None,
+            layer: Layer::default(),
+            override_redirect: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum Protocol {
     X11,
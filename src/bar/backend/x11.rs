@@ -1,29 +1,43 @@
 #![allow(unsafe_code)]
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    rc::Rc,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
 
 use crate::{
-    bar::{self, Bar, PagbarConfig, Position},
+    bar::{Align, Autohide, Bar, Layer, MonitorSelector, PagbarConfig, Position},
+    ipc,
     layout::Layout,
+    popup::{Menu, Tooltip},
 };
 use egui_winit::winit::{
     self,
     event::WindowEvent,
-    event_loop::{ControlFlow, EventLoop, EventLoopProxy, EventLoopWindowTarget},
+    event_loop::{ControlFlow, EventLoopProxy, EventLoopWindowTarget},
     monitor::MonitorHandle,
-    platform::x11::WindowBuilderExtX11,
-    window::Window,
-    window::WindowId,
+    platform::x11::{WindowBuilderExtX11, WindowExtX11, XWindowType},
+    window::{Window, WindowId, WindowLevel},
 };
 
+mod ewmh;
+mod fullscreen;
+mod popup;
+mod randr;
+
 enum UserEvent {
     RequestRedraw(WindowId),
+    /// Redraw once the instant has passed, from `request_repaint_after`
+    RepaintAt(WindowId, Instant),
+    MonitorsChanged,
+    Ipc(ipc::Request),
+    /// Area of the focused window while it's fullscreen
+    Fullscreen(Option<fullscreen::Area>),
 }
 
 pub fn run(config: PagbarConfig) {
@@ -32,7 +46,13 @@ pub fn run(config: PagbarConfig) {
 
     let event_loop = winit::event_loop::EventLoopBuilder::with_user_event().build();
     let repaint_signal = RepaintSignal(Arc::new(Mutex::new(event_loop.create_proxy())));
-    let mut bars = create_bars(&event_loop, repaint_signal, config);
+    let x11 = Rc::new(ewmh::Connection::new().expect("unable to connect to the X server"));
+    let mut bars = create_bars(&event_loop, &repaint_signal, &x11, &config);
+    randr::watch(event_loop.create_proxy());
+    fullscreen::watch(event_loop.create_proxy());
+    let ipc_proxy = event_loop.create_proxy();
+    let mut socket =
+        ipc::listen(move |request| ipc_proxy.send_event(UserEvent::Ipc(request)).is_ok());
     let mut window_map = HashMap::<WindowId, BarWindowId>::new();
 
     event_loop.run(move |event, event_loop, control_flow| match event {
@@ -42,7 +62,7 @@ pub fn run(config: PagbarConfig) {
                 .copied()
                 .and_then(|id| bars.get_mut(&id))
             {
-                bar.on_redraw_request();
+                bar.on_redraw_request(window_id, event_loop, &mut window_map);
             }
         }
         winit::event::Event::WindowEvent { window_id, event } => {
@@ -51,7 +71,7 @@ pub fn run(config: PagbarConfig) {
                 .copied()
                 .and_then(|id| bars.get_mut(&id))
             {
-                bar.on_window_event(event, control_flow, &mut window_map);
+                bar.on_window_event(window_id, event, control_flow, &mut window_map);
             }
         }
         winit::event::Event::UserEvent(UserEvent::RequestRedraw(window_id)) => {
@@ -63,6 +83,34 @@ pub fn run(config: PagbarConfig) {
                 bar.on_user_event();
             }
         }
+        winit::event::Event::UserEvent(UserEvent::RepaintAt(window_id, at)) => {
+            if let Some(bar) = window_map
+                .get(&window_id)
+                .copied()
+                .and_then(|id| bars.get_mut(&id))
+            {
+                bar.schedule_repaint(at);
+            }
+        }
+        winit::event::Event::UserEvent(UserEvent::MonitorsChanged) => {
+            update_bars(
+                event_loop,
+                &repaint_signal,
+                &x11,
+                &config,
+                &mut bars,
+                &mut window_map,
+            );
+        }
+        winit::event::Event::UserEvent(UserEvent::Fullscreen(area)) => {
+            for bar in bars.values_mut() {
+                bar.set_covered(area);
+            }
+        }
+        winit::event::Event::UserEvent(UserEvent::Ipc(request)) => {
+            let reply = handle_ipc(request.command, &mut bars, control_flow);
+            let _ = request.reply.send(reply);
+        }
         winit::event::Event::Suspended => {
             for (_, bar) in bars.iter_mut() {
                 bar.on_suspend(&mut window_map);
@@ -73,55 +121,243 @@ pub fn run(config: PagbarConfig) {
                 window.on_resume(event_loop, &mut window_map);
             }
         }
+        winit::event::Event::LoopDestroyed => {
+            // winit exits the process without dropping what the loop owns
+            drop(socket.take());
+        }
         winit::event::Event::MainEventsCleared => {
-            for (_, window) in bars.iter_mut() {
-                window.on_main_events_cleared();
+            // sleep until the earliest repaint a bar asked for
+            let now = Instant::now();
+            let next = bars
+                .values_mut()
+                .filter_map(|window| window.on_main_events_cleared(now))
+                .min();
+            if !matches!(control_flow, ControlFlow::ExitWithCode(_)) {
+                match next {
+                    Some(at) => control_flow.set_wait_until(at),
+                    None => control_flow.set_wait(),
+                }
             }
         }
         _ => (),
     });
 }
 
-fn create_bars<'a>(
-    event_loop: &EventLoop<UserEvent>,
-    repaint_signal: RepaintSignal,
-    config: PagbarConfig,
+fn handle_ipc(
+    command: ipc::Command,
+    bars: &mut HashMap<BarWindowId, BarWindow>,
+    control_flow: &mut ControlFlow,
+) -> Result<String, String> {
+    match command {
+        ipc::Command::Action { module, action } => {
+            let results = bars
+                .values_mut()
+                .map(|bar| bar.action(&module, &action))
+                .collect::<Vec<_>>();
+
+            if results.iter().any(Result::is_ok) {
+                Ok(String::new())
+            } else {
+                results
+                    .into_iter()
+                    .find_map(Result::err)
+                    .map_or(Err("no bars are open".into()), Err)
+            }
+        }
+        ipc::Command::Dump => {
+            // every bar has the same modules, the first of each name is enough
+            let mut modules = bars
+                .values_mut()
+                .flat_map(|bar| bar.layout.status())
+                .collect::<Vec<_>>();
+            let mut seen = HashSet::new();
+            modules.retain(|module| seen.insert(module.name.clone()));
+            serde_json::to_string(&modules).map_err(|err| err.to_string())
+        }
+        ipc::Command::Show => {
+            let mut revealed = false;
+            for bar in bars.values_mut() {
+                revealed |= bar.reveal();
+            }
+            if revealed {
+                Ok(String::new())
+            } else {
+                Err("no bar autohides".into())
+            }
+        }
+        ipc::Command::Quit => {
+            *control_flow = ControlFlow::Exit;
+            Ok(String::new())
+        }
+    }
+}
+
+fn create_bars(
+    event_loop: &EventLoopWindowTarget<UserEvent>,
+    repaint_signal: &RepaintSignal,
+    x11: &Rc<ewmh::Connection>,
+    config: &PagbarConfig,
 ) -> HashMap<BarWindowId, BarWindow> {
     let mut bars = HashMap::new();
 
-    for (bar, layout) in config {
-        let monitor = event_loop
-            .available_monitors()
-            .nth(bar.monitor)
-            .unwrap_or_else(|| panic!("No monitors found"));
-        let bar_window = BarWindow::new(&event_loop, repaint_signal.clone(), monitor, bar, layout);
+    for (rule, (bar, layout_factory)) in config.iter().enumerate() {
+        let monitors = match select_monitors(event_loop, &bar.monitor) {
+            Ok(monitors) => monitors,
+            Err(err) => {
+                log::warn!("{err}, \"{}\" waits for it to be connected", bar.title);
+                continue;
+            }
+        };
+
+        for monitor in monitors {
+            let bar_window = BarWindow::new(
+                event_loop,
+                repaint_signal.clone(),
+                x11.clone(),
+                rule,
+                monitor,
+                bar.clone(),
+                bar.create_layout(*layout_factory),
+            );
 
-        bars.insert(bar_window.id, bar_window);
+            bars.insert(bar_window.id, bar_window);
+        }
     }
     bars
 }
 
+/// Reconcile bars with the current monitors: follow geometry changes,
+/// tear down bars whose monitor vanished and spawn bars on new outputs
+fn update_bars(
+    event_loop: &EventLoopWindowTarget<UserEvent>,
+    repaint_signal: &RepaintSignal,
+    x11: &Rc<ewmh::Connection>,
+    config: &PagbarConfig,
+    bars: &mut HashMap<BarWindowId, BarWindow>,
+    window_map: &mut HashMap<WindowId, BarWindowId>,
+) {
+    for (rule, (bar, layout_factory)) in config.iter().enumerate() {
+        let mut monitors = select_monitors(event_loop, &bar.monitor).unwrap_or_default();
+
+        bars.retain(|_, bar_window| {
+            if bar_window.rule != rule {
+                return true;
+            }
+
+            let name = bar_window.monitor.name();
+            match monitors.iter().position(|monitor| monitor.name() == name) {
+                Some(index) => {
+                    bar_window.set_monitor(monitors.remove(index));
+                    true
+                }
+                None => {
+                    log::info!("monitor of \"{}\" disconnected", bar_window.bar.title);
+                    bar_window.destroy(window_map);
+                    false
+                }
+            }
+        });
+
+        for monitor in monitors {
+            let mut bar_window = BarWindow::new(
+                event_loop,
+                repaint_signal.clone(),
+                x11.clone(),
+                rule,
+                monitor,
+                bar.clone(),
+                bar.create_layout(*layout_factory),
+            );
+            bar_window.on_resume(event_loop, window_map);
+
+            bars.insert(bar_window.id, bar_window);
+        }
+    }
+}
+
+fn select_monitors(
+    event_loop: &EventLoopWindowTarget<UserEvent>,
+    selector: &MonitorSelector,
+) -> Result<Vec<MonitorHandle>, String> {
+    let monitors: Vec<_> = match selector {
+        MonitorSelector::Index(index) => event_loop
+            .available_monitors()
+            .nth(*index)
+            .into_iter()
+            .collect(),
+        MonitorSelector::Name(name) => event_loop
+            .available_monitors()
+            .filter(|monitor| monitor.name().as_ref() == Some(name))
+            .collect(),
+        MonitorSelector::Primary => event_loop.primary_monitor().into_iter().collect(),
+        MonitorSelector::All => event_loop.available_monitors().collect(),
+    };
+
+    if monitors.is_empty() {
+        let available = event_loop
+            .available_monitors()
+            .enumerate()
+            .map(|(index, monitor)| {
+                format!("{index}: {}", monitor.name().unwrap_or("unnamed".into()))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(format!(
+            "no output matches monitor {selector} (available outputs: {available})"
+        ));
+    }
+    Ok(monitors)
+}
+
 fn window_builder(
     x: i32,
     y: i32,
     width: u32,
     height: u32,
-    title: String,
+    bar: &Bar,
 ) -> winit::window::WindowBuilder {
+    let level = match bar.layer {
+        Layer::Top | Layer::Overlay => WindowLevel::AlwaysOnTop,
+        Layer::Bottom => WindowLevel::AlwaysOnBottom,
+    };
     winit::window::WindowBuilder::new()
         .with_resizable(false)
+        .with_transparent(bar.alpha())
         .with_position(winit::dpi::PhysicalPosition::new(x, y))
-        .with_x11_window_type(vec![winit::platform::x11::XWindowType::Dock])
+        .with_x11_window_type(vec![XWindowType::Dock])
+        .with_window_level(level)
+        .with_override_redirect(bar.override_redirect)
+        // mapped once the hints are set
+        .with_visible(false)
         .with_inner_size(winit::dpi::PhysicalSize { width, height })
-        .with_title(title)
+        .with_title(bar.title.clone())
 }
 
+/// How often bars the window manager doesn't stack restore their layer
+const RESTACK_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Clone)]
 struct RepaintSignal(Arc<Mutex<EventLoopProxy<UserEvent>>>);
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct BarWindowId(u64);
 
+/// Popups a bar shows next to its modules, each in its own window
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PopupKind {
+    Tooltip,
+    Menu,
+}
+
+impl PopupKind {
+    fn window_type(self) -> XWindowType {
+        match self {
+            PopupKind::Tooltip => XWindowType::Tooltip,
+            PopupKind::Menu => XWindowType::PopupMenu,
+        }
+    }
+}
+
 struct BarWindow {
     id: BarWindowId,
     ctx: egui::Context,
@@ -129,31 +365,125 @@ struct BarWindow {
     state: egui_winit::State,
     window: Option<winit::window::Window>,
     repaint_signal: RepaintSignal,
+    x11: Rc<ewmh::Connection>,
+    /// Index of the config entry this window was spawned from
+    rule: usize,
     bar: Bar,
     layout: Box<dyn Layout>,
     monitor: MonitorHandle,
+    tooltip_window: Option<popup::PopupWindow>,
+    tooltip: Option<Tooltip>,
+    menu_window: Option<popup::PopupWindow>,
+    menu: Option<Menu>,
+    autohide: Option<AutohideState>,
+    /// Hidden behind a fullscreen window on its monitor
+    covered: bool,
+    /// Last time an override-redirect bar restored its layer
+    restacked: Option<Instant>,
+    /// Earliest delayed repaint requested by egui or a module
+    repaint_at: Option<Instant>,
+}
+
+/// Pixels of an autohiding bar left on screen while it is hidden
+const HIDDEN_SIZE: u32 = 2;
+
+/// Where an autohiding bar is between hidden and shown
+struct AutohideState {
+    config: Autohide,
+    /// 0 while hidden, 1 while shown
+    reveal: f32,
+    shown: bool,
+    hovered: bool,
+    /// When the bar starts hiding, unless it's hovered by then
+    hide_at: Option<Instant>,
+    last_step: Instant,
+}
+
+impl AutohideState {
+    fn new(config: Autohide) -> Self {
+        let now = Instant::now();
+        // start shown so the bar can be found
+        Self {
+            config,
+            reveal: 1.,
+            shown: true,
+            hovered: false,
+            hide_at: Some(now + Duration::from_secs_f32(config.delay)),
+            last_step: now,
+        }
+    }
+
+    /// Advance the animation, `keep` holds the bar open e.g. for a menu.
+    /// Returns when to step again, if the bar isn't at rest
+    fn step(&mut self, keep: bool) -> Option<Duration> {
+        let now = Instant::now();
+        let elapsed = now - std::mem::replace(&mut self.last_step, now);
+
+        if self.hovered || keep {
+            self.shown = true;
+            self.hide_at = None;
+        } else if self.shown && self.hide_at.is_none() {
+            self.hide_at = Some(now + Duration::from_secs_f32(self.config.delay));
+        }
+        if self.hide_at.is_some_and(|hide_at| now >= hide_at) {
+            self.shown = false;
+            self.hide_at = None;
+        }
+
+        let target = if self.shown { 1. } else { 0. };
+        let step = if self.config.duration > 0. {
+            elapsed.as_secs_f32() / self.config.duration
+        } else {
+            1.
+        };
+        self.reveal = if target > self.reveal {
+            (self.reveal + step).min(target)
+        } else {
+            (self.reveal - step).max(target)
+        };
+
+        if self.reveal != target {
+            Some(Duration::ZERO)
+        } else {
+            self.hide_at.map(|hide_at| hide_at - now)
+        }
+    }
 }
 
 impl BarWindow {
     pub fn new(
-        event_loop: &EventLoop<UserEvent>,
+        event_loop: &EventLoopWindowTarget<UserEvent>,
         repaint_signal: RepaintSignal,
+        x11: Rc<ewmh::Connection>,
+        rule: usize,
         monitor: MonitorHandle,
-        bar: Bar,
+        mut bar: Bar,
         layout: Box<dyn Layout>,
     ) -> Self {
+        if bar.alpha() && !x11.compositor_running() {
+            // without a compositor the corners would be drawn black
+            log::warn!(
+                "no compositor running, \"{}\" will be opaque with square corners",
+                bar.title
+            );
+            bar.transparent = false;
+            bar.corner_radius = 0.;
+        }
+
         static ID: AtomicU64 = AtomicU64::new(0);
         let id = BarWindowId(ID.fetch_add(1, Ordering::SeqCst));
 
         let ctx = egui::Context::default();
-        let state = egui_winit::State::new(&event_loop);
+        ctx.set_fonts((*bar.fonts).clone());
+        let state = egui_winit::State::new(event_loop);
 
         let config = egui_wgpu::WgpuConfiguration {
             supported_backends: wgpu::Backends::PRIMARY,
             ..Default::default()
         };
 
-        let painter = egui_wgpu::winit::Painter::new(config, 1, None, false);
+        let painter = egui_wgpu::winit::Painter::new(config, 1, None, bar.alpha());
+        let autohide = bar.autohide.map(AutohideState::new);
 
         Self {
             id,
@@ -162,16 +492,26 @@ impl BarWindow {
             painter,
             window: None,
             repaint_signal,
+            x11,
+            rule,
             bar,
             layout,
             monitor,
+            tooltip_window: None,
+            tooltip: None,
+            menu_window: None,
+            menu: None,
+            autohide,
+            covered: false,
+            restacked: None,
+            repaint_at: None,
         }
     }
 
     fn create_window(&mut self, event_loop: &EventLoopWindowTarget<UserEvent>) -> Window {
-        let (x, y, w, h) = self.position();
-        let window = window_builder(x, y, w, h, self.bar.title.clone())
-            .build(&event_loop)
+        let (x, y, w, h) = self.geometry();
+        let window = window_builder(x, y, w, h, &self.bar)
+            .build(event_loop)
             .unwrap();
 
         pollster::block_on(self.painter.set_window(Some(&window))).expect("unable to set window");
@@ -183,6 +523,10 @@ impl BarWindow {
         let pixels_per_point = window.scale_factor() as f32;
         self.state.set_pixels_per_point(pixels_per_point);
 
+        self.reserve_space(&window);
+        self.set_hints(&window);
+        window.set_visible(true);
+
         window.request_redraw();
 
         window
@@ -200,12 +544,13 @@ impl BarWindow {
                     .expect("unable to set window");
                 let window_id = w.id();
                 let repaint_signal = self.repaint_signal.clone();
-                self.ctx.set_request_repaint_callback(move |_| {
-                    let _ = repaint_signal
-                        .0
-                        .lock()
-                        .unwrap()
-                        .send_event(UserEvent::RequestRedraw(window_id));
+                self.ctx.set_request_repaint_callback(move |info| {
+                    let event = if info.after.is_zero() {
+                        UserEvent::RequestRedraw(window_id)
+                    } else {
+                        UserEvent::RepaintAt(window_id, Instant::now() + info.after)
+                    };
+                    let _ = repaint_signal.0.lock().unwrap().send_event(event);
                 });
                 window_map.insert(window_id, self.id);
                 self.window = Some(w);
@@ -216,11 +561,34 @@ impl BarWindow {
         window.request_redraw();
     }
 
-    fn on_redraw_request(&mut self) {
+    fn on_redraw_request(
+        &mut self,
+        window_id: WindowId,
+        event_loop: &EventLoopWindowTarget<UserEvent>,
+        window_map: &mut HashMap<WindowId, BarWindowId>,
+    ) {
+        if let Some(kind) = self.popup_kind(window_id) {
+            self.redraw_popup(kind);
+            return;
+        }
+
+        if self.covered {
+            return;
+        }
+        self.repaint_at = self.repaint_at.filter(|&at| at > Instant::now());
+        self.update_autohide();
+        self.restack();
         if let Some(window) = self.window.as_ref() {
             let raw_input = self.state.take_egui_input(window);
 
             let output = self.ctx.run(raw_input, |ctx| {
+                if let Some((radius, fill)) = self.bar.rounded_background() {
+                    ctx.layer_painter(egui::LayerId::background()).rect_filled(
+                        ctx.screen_rect(),
+                        radius,
+                        fill,
+                    );
+                }
                 self.layout.display(ctx, &self.bar);
             });
 
@@ -229,7 +597,7 @@ impl BarWindow {
 
             self.painter.paint_and_update_textures(
                 self.state.pixels_per_point(),
-                egui::Rgba::default().to_array(),
+                self.bar.clear_color().to_array(),
                 &self.ctx.tessellate(output.shapes),
                 &output.textures_delta,
                 false,
@@ -237,39 +605,249 @@ impl BarWindow {
 
             if output.repaint_after.is_zero() {
                 window.request_redraw();
+            } else if let Some(at) = Instant::now().checked_add(output.repaint_after) {
+                self.schedule_repaint(at);
             }
+
+            self.tooltip = crate::popup::take_tooltip(&self.ctx);
+            self.menu = crate::popup::take_menu(&self.ctx);
+            self.update_popup(PopupKind::Tooltip, event_loop, window_map);
+            self.update_popup(PopupKind::Menu, event_loop, window_map);
         }
     }
 
-    fn on_user_event(&mut self) {
+    fn popup_kind(&self, window_id: WindowId) -> Option<PopupKind> {
+        [PopupKind::Tooltip, PopupKind::Menu]
+            .into_iter()
+            .find(|&kind| self.popup_window(kind).map(|popup| popup.id()) == Some(window_id))
+    }
+
+    fn popup_window(&self, kind: PopupKind) -> Option<&popup::PopupWindow> {
+        match kind {
+            PopupKind::Tooltip => self.tooltip_window.as_ref(),
+            PopupKind::Menu => self.menu_window.as_ref(),
+        }
+    }
+
+    /// Module rect the popup was requested for during the last frame
+    fn popup_anchor(&self, kind: PopupKind) -> Option<egui::Rect> {
+        match kind {
+            PopupKind::Tooltip => self.tooltip.as_ref().map(|tooltip| tooltip.anchor),
+            PopupKind::Menu => self.menu.as_ref().map(|menu| menu.anchor),
+        }
+    }
+
+    /// Show a popup window next to the module that requested it, or hide it
+    fn update_popup(
+        &mut self,
+        kind: PopupKind,
+        event_loop: &EventLoopWindowTarget<UserEvent>,
+        window_map: &mut HashMap<WindowId, BarWindowId>,
+    ) {
+        let Some(anchor) = self.popup_anchor(kind) else {
+            if let Some(popup) = self.popup_window(kind) {
+                popup.hide();
+            }
+            return;
+        };
+
+        if self.popup_window(kind).is_none() {
+            match popup::PopupWindow::new(event_loop, &self.bar, kind.window_type()) {
+                Ok(popup) => {
+                    window_map.insert(popup.id(), self.id);
+                    match kind {
+                        PopupKind::Tooltip => self.tooltip_window = Some(popup),
+                        PopupKind::Menu => self.menu_window = Some(popup),
+                    }
+                }
+                Err(err) => {
+                    log::warn!("unable to create popup for \"{}\": {err}", self.bar.title);
+                    return;
+                }
+            }
+        }
+
+        if let Some(size) = self.draw_popup(kind, false) {
+            let position = self.popup_position(anchor, size);
+            if let Some(popup) = self.popup_window(kind) {
+                popup.show(position, size);
+            }
+        }
+    }
+
+    fn redraw_popup(&mut self, kind: PopupKind) {
+        let (Some(size), Some(anchor)) = (self.draw_popup(kind, true), self.popup_anchor(kind))
+        else {
+            return;
+        };
+
+        // contents of menus can change size after input
+        let position = self.popup_position(anchor, size);
+        if let Some(popup) = self.popup_window(kind) {
+            if popup.place(position, size) {
+                popup.request_redraw();
+            }
+        }
+    }
+
+    /// Measure or paint a popup, returns the pixel size of its contents
+    fn draw_popup(
+        &mut self,
+        kind: PopupKind,
+        paint: bool,
+    ) -> Option<winit::dpi::PhysicalSize<u32>> {
+        let Self {
+            bar,
+            layout,
+            tooltip_window,
+            tooltip,
+            menu_window,
+            menu,
+            ..
+        } = self;
+
+        let draw = |popup: &mut popup::PopupWindow, add_contents: &mut dyn FnMut(&mut egui::Ui)| {
+            if paint {
+                popup.redraw(add_contents)
+            } else {
+                popup.measure(add_contents)
+            }
+        };
+
+        match kind {
+            PopupKind::Tooltip => {
+                let (popup, tooltip) = (tooltip_window.as_mut()?, tooltip.as_ref()?);
+                let job = tooltip.content.layout_job(&tooltip.font, egui::Align::LEFT);
+                Some(draw(popup, &mut |ui| {
+                    ui.add(egui::Label::new(job.clone()).wrap(false));
+                }))
+            }
+            PopupKind::Menu => {
+                let (popup, menu) = (menu_window.as_mut()?, menu.as_ref()?);
+                Some(draw(popup, &mut |ui| layout.popup(ui, bar, &menu.module)))
+            }
+        }
+    }
+
+    /// Place a popup beside `anchor` on the side of the bar facing the
+    /// screen, kept within the monitor
+    fn popup_position(
+        &self,
+        anchor: egui::Rect,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> winit::dpi::PhysicalPosition<i32> {
+        const GAP: i32 = 4;
+        let (x, y, w, h) = self.position();
+        let anchor = anchor.center().to_vec2() * self.state.pixels_per_point();
+        let (anchor_x, anchor_y) = (x + anchor.x as i32, y + anchor.y as i32);
+        let (width, height) = (size.width as i32, size.height as i32);
+
+        let (popup_x, popup_y) = match self.bar.position {
+            Position::Bottom => (anchor_x - width / 2, y - height - GAP),
+            Position::Top => (anchor_x - width / 2, y + h as i32 + GAP),
+            Position::Left => (x + w as i32 + GAP, anchor_y - height / 2),
+            Position::Right => (x - width - GAP, anchor_y - height / 2),
+        };
+
+        let (mx, my) = (self.monitor.position().x, self.monitor.position().y);
+        let (mw, mh) = (
+            self.monitor.size().width as i32,
+            self.monitor.size().height as i32,
+        );
+        winit::dpi::PhysicalPosition::new(
+            popup_x.min(mx + mw - width).max(mx),
+            popup_y.min(my + mh - height).max(my),
+        )
+    }
+
+    fn action(&mut self, module: &str, action: &str) -> Result<(), String> {
+        self.layout.action(module, action)?;
         if let Some(window) = self.window.as_ref() {
             window.request_redraw();
         }
+        Ok(())
     }
 
-    fn on_main_events_cleared(&mut self) {
+    fn on_user_event(&mut self) {
         if let Some(window) = self.window.as_ref() {
             window.request_redraw();
         }
     }
 
+    fn schedule_repaint(&mut self, at: Instant) {
+        self.repaint_at = Some(self.repaint_at.map_or(at, |repaint_at| repaint_at.min(at)));
+    }
+
+    /// Redraw if a delayed repaint is due, returns when the next one is
+    fn on_main_events_cleared(&mut self, now: Instant) -> Option<Instant> {
+        if self.repaint_at.is_some_and(|at| at <= now) {
+            self.repaint_at = None;
+            if let Some(window) = self.window.as_ref() {
+                window.request_redraw();
+            }
+        }
+        self.repaint_at
+    }
+
+    /// Follow the monitor to its new geometry
+    fn set_monitor(&mut self, monitor: MonitorHandle) {
+        self.monitor = monitor;
+
+        if let Some(window) = self.window.as_ref() {
+            self.place(window);
+            self.reserve_space(window);
+        }
+    }
+
+    fn destroy(&mut self, window_map: &mut HashMap<WindowId, BarWindowId>) {
+        // release the surface before the window it was created from
+        if let Err(err) = pollster::block_on(self.painter.set_window(None)) {
+            log::warn!("unable to release surface of \"{}\": {err}", self.bar.title);
+        }
+        self.on_suspend(window_map);
+    }
+
     fn on_suspend(&mut self, window_map: &mut HashMap<WindowId, BarWindowId>) {
         if let Some(window) = self.window.as_ref() {
             window_map.remove(&window.id());
         }
+        for popup in [self.tooltip_window.take(), self.menu_window.take()]
+            .into_iter()
+            .flatten()
+        {
+            window_map.remove(&popup.id());
+        }
         self.window = None;
     }
 
     fn on_window_event(
         &mut self,
+        window_id: WindowId,
         event: WindowEvent,
         control_flow: &mut ControlFlow,
         window_map: &mut HashMap<WindowId, BarWindowId>,
     ) {
+        if let Some(popup) = [self.tooltip_window.as_mut(), self.menu_window.as_mut()]
+            .into_iter()
+            .flatten()
+            .find(|popup| popup.id() == window_id)
+        {
+            popup.on_window_event(&event);
+            return;
+        }
+
         match event {
             WindowEvent::Resized(size) => {
                 self.painter.on_window_resized(size.width, size.height);
             }
+            WindowEvent::CursorEntered { .. } | WindowEvent::CursorLeft { .. } => {
+                if let Some(autohide) = self.autohide.as_mut() {
+                    autohide.hovered = matches!(event, WindowEvent::CursorEntered { .. });
+                    if let Some(window) = self.window.as_ref() {
+                        window.request_redraw();
+                    }
+                }
+            }
             WindowEvent::CloseRequested => {
                 self.on_suspend(window_map);
                 if window_map.is_empty() {
@@ -288,33 +866,271 @@ impl BarWindow {
         }
     }
 
-    fn position(&mut self) -> (i32, i32, u32, u32) {
+    fn position(&self) -> (i32, i32, u32, u32) {
         let monitor = &self.monitor;
+        let (mx, my) = (monitor.position().x, monitor.position().y);
+        let (mw, mh) = (monitor.size().width, monitor.size().height);
+        let margin = self.bar.margin as u32;
+        let size = self.bar.size as u32;
+
+        // place the bar along the edge, leaving the margin on both ends
+        let along = |extent: u32| {
+            let available = extent.saturating_sub(margin * 2);
+            let length = self.bar.length.resolve(available);
+            let offset = match self.bar.align {
+                Align::Start => 0,
+                Align::Center => (available - length) / 2,
+                Align::End => available - length,
+            };
+            ((margin + offset) as i32, length)
+        };
+
+        match self.bar.position {
+            Position::Left => {
+                let (offset, length) = along(mh);
+                (mx + margin as i32, my + offset, size, length)
+            }
+            Position::Right => {
+                let (offset, length) = along(mh);
+                (
+                    mx + mw as i32 - size as i32 - margin as i32,
+                    my + offset,
+                    size,
+                    length,
+                )
+            }
+            Position::Top => {
+                let (offset, length) = along(mw);
+                (mx + offset, my + margin as i32, length, size)
+            }
+            Position::Bottom => {
+                let (offset, length) = along(mw);
+                (
+                    mx + offset,
+                    my + mh as i32 - size as i32 - margin as i32,
+                    length,
+                    size,
+                )
+            }
+        }
+    }
+
+    /// Where the window is, the bar's place moved towards the edge
+    /// while it autohides
+    fn geometry(&self) -> (i32, i32, u32, u32) {
+        let (x, y, w, h) = self.position();
+        let Some(autohide) = &self.autohide else {
+            return (x, y, w, h);
+        };
+        let (mx, my) = (self.monitor.position().x, self.monitor.position().y);
+        let (mw, mh) = (self.monitor.size().width, self.monitor.size().height);
+        let hidden = HIDDEN_SIZE as i32;
+
+        // fully hidden the window shrinks to a strip, so it doesn't
+        // stick out onto a neighbouring monitor
+        if autohide.reveal <= 0. {
+            return match self.bar.position {
+                Position::Left => (mx, y, HIDDEN_SIZE, h),
+                Position::Right => (mx + mw as i32 - hidden, y, HIDDEN_SIZE, h),
+                Position::Top => (x, my, w, HIDDEN_SIZE),
+                Position::Bottom => (x, my + mh as i32 - hidden, w, HIDDEN_SIZE),
+            };
+        }
+
+        let distance = self.bar.size as f32 + self.bar.margin as f32 - HIDDEN_SIZE as f32;
+        let offset = (distance * (1. - autohide.reveal)).round() as i32;
+        match self.bar.position {
+            Position::Left => (x - offset, y, w, h),
+            Position::Right => (x + offset, y, w, h),
+            Position::Top => (x, y - offset, w, h),
+            Position::Bottom => (x, y + offset, w, h),
+        }
+    }
+
+    fn place(&self, window: &Window) {
+        let (x, y, w, h) = self.geometry();
+        window.set_outer_position(winit::dpi::PhysicalPosition::new(x, y));
+        window.set_inner_size(winit::dpi::PhysicalSize::new(w, h));
+    }
+
+    /// Keep an override-redirect bar in its layer, there is no window
+    /// manager to do it and other windows get raised above it
+    fn restack(&mut self) {
+        if !self.bar.override_redirect
+            || self
+                .restacked
+                .is_some_and(|restacked| restacked.elapsed() < RESTACK_INTERVAL)
+        {
+            return;
+        }
+        self.restacked = Some(Instant::now());
+
+        let Some(xwindow) = self.window.as_ref().and_then(|window| window.xlib_window()) else {
+            return;
+        };
+        let above = self.bar.layer != Layer::Bottom;
+        if let Err(err) = self.x11.restack(xwindow as u32, above) {
+            log::warn!("unable to restack \"{}\": {err}", self.bar.title);
+        }
+    }
+
+    /// Put the bar above every other window, window managers stack
+    /// fullscreen windows above ones that are only "above"
+    fn raise(&self) {
+        let Some(xwindow) = self.window.as_ref().and_then(|window| window.xlib_window()) else {
+            return;
+        };
+        if let Err(err) = self.x11.restack(xwindow as u32, true) {
+            log::warn!("unable to raise \"{}\": {err}", self.bar.title);
+        }
+    }
+
+    /// Unmap the bar while a fullscreen window is on its monitor,
+    /// unless it's an overlay
+    fn set_covered(&mut self, fullscreen: Option<fullscreen::Area>) {
+        let (mx, my) = (self.monitor.position().x, self.monitor.position().y);
+        let (mw, mh) = (self.monitor.size().width, self.monitor.size().height);
+        let covered = fullscreen.is_some_and(|area| {
+            let (cx, cy) = (
+                area.x + area.width as i32 / 2,
+                area.y + area.height as i32 / 2,
+            );
+            (mx..mx + mw as i32).contains(&cx) && (my..my + mh as i32).contains(&cy)
+        });
+        // overlay bars stay, raised above the fullscreen window
+        if self.bar.layer == Layer::Overlay {
+            if covered {
+                self.raise();
+            }
+            return;
+        }
+        if covered == self.covered {
+            return;
+        }
+        self.covered = covered;
+
+        if covered {
+            self.tooltip = None;
+            self.menu = None;
+            for popup in [self.tooltip_window.as_ref(), self.menu_window.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                popup.hide();
+            }
+        }
+        if let Some(window) = self.window.as_ref() {
+            if !covered {
+                self.set_hints(window);
+            }
+            window.set_visible(!covered);
+            if !covered {
+                window.request_redraw();
+            }
+        }
+    }
+
+    /// Show an autohiding bar for a while, false if it doesn't autohide
+    fn reveal(&mut self) -> bool {
+        let Some(autohide) = self.autohide.as_mut() else {
+            return false;
+        };
+        autohide.shown = true;
+        autohide.hide_at = Some(Instant::now() + Duration::from_secs_f32(autohide.config.delay));
+        if let Some(window) = self.window.as_ref() {
+            window.request_redraw();
+        }
+        true
+    }
+
+    /// Step an autohiding bar's animation, moving the window and
+    /// giving up its space while it isn't fully shown
+    fn update_autohide(&mut self) {
+        let keep = self.menu.is_some();
+        let Some(autohide) = self.autohide.as_mut() else {
+            return;
+        };
+        let before = autohide.reveal;
+        let next = autohide.step(keep);
+        let after = autohide.reveal;
+
+        let Some(window) = self.window.as_ref() else {
+            return;
+        };
+        match next {
+            Some(Duration::ZERO) => window.request_redraw(),
+            Some(after) => self.ctx.request_repaint_after(after),
+            None => {}
+        }
+        if before != after {
+            self.place(window);
+            if before == 1. || after == 1. {
+                self.reserve_space(window);
+            }
+        }
+    }
+
+    fn set_hints(&self, window: &Window) {
+        if let Some(xwindow) = window.xlib_window() {
+            let hints = self
+                .x11
+                .set_bar_hints(xwindow as u32, &self.bar.name, self.bar.layer);
+            if let Err(err) = hints {
+                log::warn!("unable to set hints of \"{}\": {err}", self.bar.title);
+            }
+        }
+    }
+
+    fn reserve_space(&self, window: &Window) {
+        if let Some(xwindow) = window.xlib_window() {
+            let hidden = self
+                .autohide
+                .as_ref()
+                .is_some_and(|autohide| autohide.reveal < 1.);
+            let struts = if hidden {
+                ewmh::Struts::default()
+            } else {
+                self.struts(self.position())
+            };
+            if let Err(err) = self.x11.set_struts(xwindow as u32, &struts) {
+                log::warn!("unable to reserve space for \"{}\": {err}", self.bar.title);
+            }
+        }
+    }
+
+    /// Reserve the bar and its margin from the root window edge,
+    /// limited to the span the bar actually covers
+    fn struts(&self, (x, y, w, h): (i32, i32, u32, u32)) -> ewmh::Struts {
+        let (root_width, root_height) = self.x11.root_size();
+        let margin = self.bar.margin as u32;
+        let (x, y) = (x.max(0) as u32, y.max(0) as u32);
+        let (x_end, y_end) = ((x + w).saturating_sub(1), (y + h).saturating_sub(1));
+
         match self.bar.position {
-            Position::Left => (
-                monitor.position().x,
-                monitor.position().y,
-                self.bar.size as u32,
-                monitor.size().height,
-            ),
-            Position::Right => (
-                monitor.position().x + monitor.size().width as i32 - self.bar.size as i32,
-                monitor.position().y,
-                self.bar.size as u32,
-                monitor.size().height,
-            ),
-            Position::Top => (
-                monitor.position().x,
-                monitor.position().y,
-                monitor.size().width,
-                self.bar.size as u32,
-            ),
-            Position::Bottom => (
-                monitor.position().x,
-                monitor.position().y + monitor.size().height as i32 - self.bar.size as i32,
-                monitor.size().width,
-                self.bar.size as u32,
-            ),
+            Position::Left => ewmh::Struts {
+                left: x + w + margin,
+                left_start_y: y,
+                left_end_y: y_end,
+                ..Default::default()
+            },
+            Position::Right => ewmh::Struts {
+                right: root_width.saturating_sub(x) + margin,
+                right_start_y: y,
+                right_end_y: y_end,
+                ..Default::default()
+            },
+            Position::Top => ewmh::Struts {
+                top: y + h + margin,
+                top_start_x: x,
+                top_end_x: x_end,
+                ..Default::default()
+            },
+            Position::Bottom => ewmh::Struts {
+                bottom: root_height.saturating_sub(y) + margin,
+                bottom_start_x: x,
+                bottom_end_x: x_end,
+                ..Default::default()
+            },
         }
     }
 }
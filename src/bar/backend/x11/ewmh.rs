@@ -0,0 +1,201 @@
+use crate::bar::Layer;
+use x11rb::{
+    connection::Connection as _,
+    errors::ReplyError,
+    protocol::xproto::{AtomEnum, ConfigureWindowAux, ConnectionExt as _, PropMode, StackMode},
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+};
+
+x11rb::atom_manager! {
+    pub Atoms: AtomsCookie {
+        _NET_WM_STRUT,
+        _NET_WM_STRUT_PARTIAL,
+        _NET_WM_PID,
+        _NET_WM_DESKTOP,
+        _NET_WM_STATE,
+        _NET_WM_STATE_STICKY,
+        _NET_WM_STATE_SKIP_TASKBAR,
+        _NET_WM_STATE_SKIP_PAGER,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_BELOW,
+    }
+}
+
+/// `WM_CLASS` class of every bar, capitalised like ICCCM expects,
+/// the instance is the bar's name
+const CLASS: &str = "Pagbar";
+
+/// `_NET_WM_DESKTOP` of windows shown on every desktop
+const ALL_DESKTOPS: u32 = 0xFFFFFFFF;
+
+/// Space a bar reserves along the edges of the root window
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Struts {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+    pub left_start_y: u32,
+    pub left_end_y: u32,
+    pub right_start_y: u32,
+    pub right_end_y: u32,
+    pub top_start_x: u32,
+    pub top_end_x: u32,
+    pub bottom_start_x: u32,
+    pub bottom_end_x: u32,
+}
+
+impl Struts {
+    fn partial(&self) -> [u32; 12] {
+        [
+            self.left,
+            self.right,
+            self.top,
+            self.bottom,
+            self.left_start_y,
+            self.left_end_y,
+            self.right_start_y,
+            self.right_end_y,
+            self.top_start_x,
+            self.top_end_x,
+            self.bottom_start_x,
+            self.bottom_end_x,
+        ]
+    }
+}
+
+/// Side channel to the X server for everything winit doesn't expose
+pub struct Connection {
+    conn: RustConnection,
+    screen: usize,
+    atoms: Atoms,
+}
+
+impl Connection {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let (conn, screen) = x11rb::connect(None)?;
+        let atoms = Atoms::new(&conn)?.reply()?;
+        Ok(Self {
+            conn,
+            screen,
+            atoms,
+        })
+    }
+
+    /// Size of the root window that struts are relative to, asked for
+    /// every time since RandR resizes it when monitors change
+    pub fn root_size(&self) -> (u32, u32) {
+        let screen = &self.conn.setup().roots[self.screen];
+        let geometry = self
+            .conn
+            .get_geometry(screen.root)
+            .map_err(ReplyError::from)
+            .and_then(|cookie| cookie.reply());
+        match geometry {
+            Ok(geometry) => (geometry.width as u32, geometry.height as u32),
+            Err(err) => {
+                log::warn!("unable to get the root window size: {err}");
+                // only correct until the first resize
+                (
+                    screen.width_in_pixels as u32,
+                    screen.height_in_pixels as u32,
+                )
+            }
+        }
+    }
+
+    /// Whether a compositing manager owns the `_NET_WM_CM_S<screen>` selection,
+    /// without one an ARGB visual is just drawn as opaque black
+    pub fn compositor_running(&self) -> bool {
+        let selection = format!("_NET_WM_CM_S{}", self.screen);
+        let owner = || -> Result<u32, ReplyError> {
+            let atom = self
+                .conn
+                .intern_atom(false, selection.as_bytes())?
+                .reply()?;
+            Ok(self.conn.get_selection_owner(atom.atom)?.reply()?.owner)
+        };
+
+        owner().map(|owner| owner != x11rb::NONE).unwrap_or(false)
+    }
+
+    pub fn set_struts(&self, window: u32, struts: &Struts) -> Result<(), ReplyError> {
+        let partial = struts.partial();
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms._NET_WM_STRUT_PARTIAL,
+            AtomEnum::CARDINAL,
+            &partial,
+        )?;
+        // older window managers only understand the plain variant
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms._NET_WM_STRUT,
+            AtomEnum::CARDINAL,
+            &partial[..4],
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Raise or lower a window the window manager doesn't stack
+    pub fn restack(&self, window: u32, above: bool) -> Result<(), ReplyError> {
+        let mode = if above {
+            StackMode::ABOVE
+        } else {
+            StackMode::BELOW
+        };
+        self.conn
+            .configure_window(window, &ConfigureWindowAux::new().stack_mode(mode))?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Hints for window manager rules and pagers, set while the window is
+    /// unmapped since window managers drop the state of withdrawn windows
+    pub fn set_bar_hints(&self, window: u32, name: &str, layer: Layer) -> Result<(), ReplyError> {
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            format!("{name}\0{CLASS}\0").as_bytes(),
+        )?;
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms._NET_WM_PID,
+            AtomEnum::CARDINAL,
+            &[std::process::id()],
+        )?;
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms._NET_WM_DESKTOP,
+            AtomEnum::CARDINAL,
+            &[ALL_DESKTOPS],
+        )?;
+
+        let mut state = vec![
+            self.atoms._NET_WM_STATE_STICKY,
+            self.atoms._NET_WM_STATE_SKIP_TASKBAR,
+            self.atoms._NET_WM_STATE_SKIP_PAGER,
+        ];
+        state.push(match layer {
+            Layer::Top | Layer::Overlay => self.atoms._NET_WM_STATE_ABOVE,
+            Layer::Bottom => self.atoms._NET_WM_STATE_BELOW,
+        });
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms._NET_WM_STATE,
+            AtomEnum::ATOM,
+            &state,
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
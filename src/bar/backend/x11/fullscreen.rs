@@ -0,0 +1,145 @@
+use egui_winit::winit::event_loop::EventLoopProxy;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xproto::{AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Window},
+        Event,
+    },
+    rust_connection::RustConnection,
+};
+
+use super::UserEvent;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_STATE,
+        _NET_WM_STATE_FULLSCREEN,
+    }
+}
+
+/// Area of a fullscreen window in root coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Area {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Tell the event loop whenever the focused window enters or leaves
+/// fullscreen, or a fullscreen window moves
+pub fn watch(proxy: EventLoopProxy<UserEvent>) {
+    std::thread::spawn(move || {
+        if let Err(err) = listen(&proxy) {
+            log::error!("fullscreen detection unavailable: {err}");
+        }
+    });
+}
+
+fn listen(proxy: &EventLoopProxy<UserEvent>) -> Result<(), Box<dyn std::error::Error>> {
+    let (conn, screen) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen].root;
+    let atoms = Atoms::new(&conn)?.reply()?;
+
+    select(&conn, root, EventMask::PROPERTY_CHANGE);
+    let mut active = active_window(&conn, root, &atoms);
+    if let Some(window) = active {
+        select(&conn, window, watched());
+    }
+    let mut fullscreen = None;
+
+    loop {
+        let area = active.and_then(|window| fullscreen_area(&conn, root, window, &atoms));
+        if area != fullscreen {
+            fullscreen = area;
+            if proxy.send_event(UserEvent::Fullscreen(area)).is_err() {
+                // event loop is gone
+                return Ok(());
+            }
+        }
+
+        match conn.wait_for_event()? {
+            Event::PropertyNotify(event)
+                if event.window == root && event.atom == atoms._NET_ACTIVE_WINDOW =>
+            {
+                let window = active_window(&conn, root, &atoms);
+                if window != active {
+                    if let Some(old) = active {
+                        select(&conn, old, EventMask::NO_EVENT);
+                    }
+                    if let Some(window) = window {
+                        select(&conn, window, watched());
+                    }
+                    active = window;
+                }
+            }
+            Event::DestroyNotify(event) if Some(event.window) == active => active = None,
+            _ => {}
+        }
+    }
+}
+
+/// Events of the active window that can change its fullscreen area
+fn watched() -> EventMask {
+    EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY
+}
+
+/// Errors are ignored, the window may already be gone
+fn select(conn: &RustConnection, window: Window, mask: EventMask) {
+    let aux = ChangeWindowAttributesAux::new().event_mask(mask);
+    if let Ok(cookie) = conn.change_window_attributes(window, &aux) {
+        cookie.ignore_error();
+    }
+    let _ = conn.flush();
+}
+
+fn active_window(conn: &RustConnection, root: Window, atoms: &Atoms) -> Option<Window> {
+    let reply = conn
+        .get_property(
+            false,
+            root,
+            atoms._NET_ACTIVE_WINDOW,
+            AtomEnum::WINDOW,
+            0,
+            1,
+        )
+        .ok()?
+        .reply()
+        .ok()?;
+    let window = reply.value32()?.next()?;
+    (window != x11rb::NONE).then_some(window)
+}
+
+/// Where `window` is if it's fullscreen
+fn fullscreen_area(
+    conn: &RustConnection,
+    root: Window,
+    window: Window,
+    atoms: &Atoms,
+) -> Option<Area> {
+    let state = conn
+        .get_property(false, window, atoms._NET_WM_STATE, AtomEnum::ATOM, 0, 32)
+        .ok()?
+        .reply()
+        .ok()?;
+    if !state
+        .value32()?
+        .any(|atom| atom == atoms._NET_WM_STATE_FULLSCREEN)
+    {
+        return None;
+    }
+
+    let geometry = conn.get_geometry(window).ok()?.reply().ok()?;
+    let origin = conn
+        .translate_coordinates(window, root, 0, 0)
+        .ok()?
+        .reply()
+        .ok()?;
+    Some(Area {
+        x: origin.dst_x as i32,
+        y: origin.dst_y as i32,
+        width: geometry.width as u32,
+        height: geometry.height as u32,
+    })
+}
@@ -0,0 +1,200 @@
+use egui_winit::winit::{
+    self,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::WindowEvent,
+    event_loop::EventLoopWindowTarget,
+    platform::x11::{WindowBuilderExtX11, XWindowType},
+    window::{Window, WindowId},
+};
+
+use super::UserEvent;
+use crate::bar::Bar;
+
+/// Override-redirect window for content that doesn't fit into the bar,
+/// it is placed by the bar and never managed by the window manager
+pub struct PopupWindow {
+    ctx: egui::Context,
+    // declared before the window, so the surface is released first
+    painter: egui_wgpu::winit::Painter,
+    state: egui_winit::State,
+    window: Window,
+    /// Texture updates from frames that were only measured
+    textures_delta: egui::TexturesDelta,
+}
+
+/// Screen size popups are laid out in before their size is known
+const MEASURE_SIZE: f32 = 4096.;
+
+impl PopupWindow {
+    pub fn new(
+        event_loop: &EventLoopWindowTarget<UserEvent>,
+        bar: &Bar,
+        window_type: XWindowType,
+    ) -> Result<Self, winit::error::OsError> {
+        let window = winit::window::WindowBuilder::new()
+            .with_title(format!("{} popup", bar.title))
+            .with_decorations(false)
+            .with_resizable(false)
+            .with_transparent(bar.transparent)
+            .with_visible(false)
+            .with_override_redirect(true)
+            .with_x11_window_type(vec![window_type])
+            .with_inner_size(PhysicalSize::new(1, 1))
+            .build(event_loop)?;
+
+        let ctx = egui::Context::default();
+        ctx.set_fonts((*bar.fonts).clone());
+        ctx.set_visuals(visuals(bar));
+
+        let config = egui_wgpu::WgpuConfiguration {
+            supported_backends: wgpu::Backends::PRIMARY,
+            ..Default::default()
+        };
+        let mut painter = egui_wgpu::winit::Painter::new(config, 1, None, bar.transparent);
+        pollster::block_on(painter.set_window(Some(&window))).expect("unable to set window");
+
+        let mut state = egui_winit::State::new(event_loop);
+        if let Some(max_size) = painter.max_texture_side() {
+            state.set_max_texture_side(max_size);
+        }
+        state.set_pixels_per_point(window.scale_factor() as f32);
+
+        Ok(Self {
+            ctx,
+            painter,
+            state,
+            window,
+            textures_delta: Default::default(),
+        })
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    /// Move and resize the window, returns true if anything changed
+    pub fn place(&self, position: PhysicalPosition<i32>, size: PhysicalSize<u32>) -> bool {
+        let mut changed = false;
+        if self.window.inner_size() != size {
+            self.window.set_inner_size(size);
+            changed = true;
+        }
+        if self.window.outer_position().ok() != Some(position) {
+            self.window.set_outer_position(position);
+            changed = true;
+        }
+        changed
+    }
+
+    pub fn show(&self, position: PhysicalPosition<i32>, size: PhysicalSize<u32>) {
+        self.place(position, size);
+        self.window.set_visible(true);
+        self.window.request_redraw();
+    }
+
+    pub fn request_redraw(&self) {
+        self.window.request_redraw();
+    }
+
+    pub fn hide(&self) {
+        self.window.set_visible(false);
+    }
+
+    /// Lay the contents out without painting them, returns the pixel
+    /// size the popup needs
+    pub fn measure(&mut self, add_contents: &mut dyn FnMut(&mut egui::Ui)) -> PhysicalSize<u32> {
+        // no events, those are left for the frame that gets painted
+        let raw_input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::Vec2::splat(MEASURE_SIZE),
+            )),
+            pixels_per_point: Some(self.state.pixels_per_point()),
+            ..Default::default()
+        };
+
+        let mut size = egui::Vec2::ZERO;
+        let output = self
+            .ctx
+            .run(raw_input, |ctx| size = contents(ctx, add_contents));
+        // fonts may have been uploaded, keep them for the next paint
+        self.textures_delta.append(output.textures_delta);
+
+        let size = size * self.state.pixels_per_point();
+        PhysicalSize::new(size.x.ceil() as u32, size.y.ceil() as u32)
+    }
+
+    /// Paint the contents, returns the pixel size they took
+    pub fn redraw(&mut self, add_contents: &mut dyn FnMut(&mut egui::Ui)) -> PhysicalSize<u32> {
+        let raw_input = self.state.take_egui_input(&self.window);
+        let mut size = egui::Vec2::ZERO;
+        let output = self
+            .ctx
+            .run(raw_input, |ctx| size = contents(ctx, add_contents));
+
+        self.state
+            .handle_platform_output(&self.window, &self.ctx, output.platform_output);
+
+        let mut textures_delta = std::mem::take(&mut self.textures_delta);
+        textures_delta.append(output.textures_delta);
+        self.painter.paint_and_update_textures(
+            self.state.pixels_per_point(),
+            egui::Rgba::TRANSPARENT.to_array(),
+            &self.ctx.tessellate(output.shapes),
+            &textures_delta,
+            false,
+        );
+
+        if output.repaint_after.is_zero() {
+            self.window.request_redraw();
+        }
+
+        let size = size * self.state.pixels_per_point();
+        PhysicalSize::new(size.x.ceil() as u32, size.y.ceil() as u32)
+    }
+
+    pub fn on_window_event(&mut self, event: &WindowEvent) {
+        if let WindowEvent::Resized(size) = event {
+            self.painter.on_window_resized(size.width, size.height);
+        }
+
+        let response = self.state.on_event(&self.ctx, event);
+        if response.repaint {
+            self.window.request_redraw();
+        }
+    }
+}
+
+/// Draw the popup frame at the top left corner, sized to what's inside
+fn contents(ctx: &egui::Context, add_contents: &mut dyn FnMut(&mut egui::Ui)) -> egui::Vec2 {
+    egui::CentralPanel::default()
+        .frame(egui::Frame::none())
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style())
+                .show(ui, add_contents)
+                .response
+                .rect
+                .size()
+        })
+        .inner
+}
+
+/// Popups use the bar colors, but always fill their frame
+fn visuals(bar: &Bar) -> egui::Visuals {
+    let mut visuals: egui::Visuals = bar.into();
+    let background = if bar.transparent {
+        bar.background
+    } else {
+        bar.background.opaque()
+    };
+
+    visuals.window_fill = background.into();
+    visuals.panel_fill = background.into();
+    visuals.window_stroke = egui::Stroke::new(1., bar.text_secondary);
+    visuals.popup_shadow = egui::epaint::Shadow::NONE;
+    if !bar.transparent {
+        // corners outside the rounding can't be see-through
+        visuals.window_rounding = egui::Rounding::none();
+    }
+    visuals
+}
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use egui_winit::winit::event_loop::EventLoopProxy;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        randr::{ConnectionExt as _, NotifyMask},
+        Event,
+    },
+};
+
+use super::UserEvent;
+
+/// Outputs emit several notifications while they are being configured
+const SETTLE: Duration = Duration::from_millis(250);
+
+/// Forward RandR screen and crtc changes to the event loop,
+/// bursts of notifications are coalesced into a single event
+pub fn watch(proxy: EventLoopProxy<UserEvent>) {
+    std::thread::spawn(move || {
+        if let Err(err) = listen(&proxy) {
+            log::error!("monitor hotplug unavailable: {err}");
+        }
+    });
+}
+
+fn listen(proxy: &EventLoopProxy<UserEvent>) -> Result<(), Box<dyn std::error::Error>> {
+    let (conn, screen) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen].root;
+
+    conn.randr_query_version(1, 2)?.reply()?;
+    conn.randr_select_input(
+        root,
+        NotifyMask::SCREEN_CHANGE | NotifyMask::CRTC_CHANGE | NotifyMask::OUTPUT_CHANGE,
+    )?;
+    conn.flush()?;
+
+    loop {
+        let event = conn.wait_for_event()?;
+        if !matches!(
+            event,
+            Event::RandrScreenChangeNotify(_) | Event::RandrNotify(_)
+        ) {
+            continue;
+        }
+
+        std::thread::sleep(SETTLE);
+        while conn.poll_for_event()?.is_some() {}
+
+        if proxy.send_event(UserEvent::MonitorsChanged).is_err() {
+            // event loop is gone
+            return Ok(());
+        }
+    }
+}
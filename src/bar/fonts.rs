@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use egui::{FontData, FontDefinitions, FontFamily};
+
+use super::user_config::UserConfigFonts;
+
+/// Build the font definitions every bar context uses, configured fonts are
+/// tried in order before egui's builtin ones so icon fonts only fill in
+/// glyphs the text fonts are missing
+pub fn load(config: &UserConfigFonts) -> FontDefinitions {
+    let mut fonts = FontDefinitions::default();
+    let builtin = fonts.families.clone();
+
+    let mut chain = |sources: &[String]| -> Vec<String> {
+        sources
+            .iter()
+            .filter_map(|source| {
+                if !fonts.font_data.contains_key(source) {
+                    let data = read(source)?;
+                    fonts.font_data.insert(source.clone(), data);
+                }
+                Some(source.clone())
+            })
+            .collect()
+    };
+
+    let proportional = chain(&config.proportional);
+    let monospace = chain(&config.monospace);
+    let families = config
+        .families
+        .iter()
+        .map(|(name, sources)| (name.clone(), chain(sources)))
+        .collect::<HashMap<_, _>>();
+
+    let fallback = |family: FontFamily| builtin.get(&family).cloned().unwrap_or_default();
+    fonts.families.insert(
+        FontFamily::Proportional,
+        [proportional.clone(), fallback(FontFamily::Proportional)].concat(),
+    );
+    fonts.families.insert(
+        FontFamily::Monospace,
+        [monospace, fallback(FontFamily::Monospace)].concat(),
+    );
+    for (name, family) in families {
+        fonts.families.insert(
+            FontFamily::Name(name.into()),
+            [
+                family,
+                proportional.clone(),
+                fallback(FontFamily::Proportional),
+            ]
+            .concat(),
+        );
+    }
+
+    fonts
+}
+
+/// Whether `family` can be used as a module `font-family`
+pub fn is_family(config: &UserConfigFonts, family: &str) -> bool {
+    matches!(family, "proportional" | "monospace") || config.families.contains_key(family)
+}
+
+pub fn family(name: &str) -> FontFamily {
+    match name {
+        "proportional" => FontFamily::Proportional,
+        "monospace" => FontFamily::Monospace,
+        name => FontFamily::Name(name.into()),
+    }
+}
+
+/// Sources are either font files or family names resolved through fontconfig
+fn read(source: &str) -> Option<FontData> {
+    let path = if is_path(source) {
+        expand_home(source)
+    } else {
+        match fc_match(source) {
+            Some(path) => path,
+            None => {
+                log::warn!("font \"{source}\" not found by fontconfig");
+                return None;
+            }
+        }
+    };
+
+    match std::fs::read(&path) {
+        Ok(bytes) => Some(FontData::from_owned(bytes)),
+        Err(err) => {
+            log::warn!("unable to load font {path:?}: {err}");
+            None
+        }
+    }
+}
+
+fn is_path(source: &str) -> bool {
+    let extension = Path::new(source)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    source.contains('/') || matches!(extension.as_deref(), Some("ttf" | "otf" | "ttc"))
+}
+
+fn expand_home(source: &str) -> PathBuf {
+    match (source.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(source),
+    }
+}
+
+/// fontconfig always answers with its closest match, only accept it
+/// when the family it picked is the one asked for
+fn fc_match(family: &str) -> Option<PathBuf> {
+    let output = Command::new("fc-match")
+        .args(["--format=%{family}\n%{file}", family])
+        .output()
+        .ok()?;
+    let output = String::from_utf8(output.stdout).ok()?;
+    let (families, file) = output.split_once('\n')?;
+
+    families
+        .split(',')
+        .any(|candidate| candidate.trim().eq_ignore_ascii_case(family))
+        .then(|| PathBuf::from(file))
+}
@@ -1,12 +1,19 @@
 use std::collections::HashMap;
 
 use super::*;
+use crate::module::{Actions, Panel, States};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct UserConfig {
     pub title: Option<String>,
     pub colors: UserConfigColors,
+    #[serde(default)]
+    pub fonts: UserConfigFonts,
     pub bar: HashMap<String, UserConfigBar>,
+    /// Ordered so modules are shown in the order they are declared
+    #[serde(default)]
+    pub module: IndexMap<String, UserConfigModule>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -14,11 +21,71 @@ pub struct UserConfigColors {
     pub background: Option<Color>,
     pub text: Option<Color>,
     pub text_secondary: Option<Color>,
+    pub warning: Option<Color>,
+    pub critical: Option<Color>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct UserConfigBar {
-    pub monitor: usize,
+    pub monitor: MonitorSelector,
     pub position: Position,
     pub size: u16,
+    #[serde(default)]
+    pub margin: u16,
+    #[serde(default)]
+    pub length: Length,
+    #[serde(default)]
+    pub align: Align,
+    #[serde(default)]
+    pub corner_radius: f32,
+    #[serde(default)]
+    pub transparent: bool,
+    /// Layout added by a plugin, or "three-split"
+    pub layout: Option<String>,
+    /// Collapse to a strip at the edge until hovered
+    #[serde(default)]
+    pub autohide: bool,
+    /// Seconds the bar stays after the pointer left it
+    pub autohide_delay: Option<f32>,
+    /// Seconds the bar takes to slide in or out
+    pub autohide_duration: Option<f32>,
+    #[serde(default)]
+    pub layer: Layer,
+    /// Place the window without the window manager
+    #[serde(default)]
+    pub override_redirect: bool,
+}
+
+/// Font files or fontconfig family names, in fallback order
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct UserConfigFonts {
+    #[serde(default)]
+    pub proportional: Vec<String>,
+    #[serde(default)]
+    pub monospace: Vec<String>,
+    #[serde(default)]
+    pub families: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UserConfigModule {
+    /// Defaults to the name of the section
+    pub kind: Option<String>,
+    pub panel: Panel,
+    pub font_size: Option<f32>,
+    pub font_family: Option<String>,
+    #[serde(default)]
+    pub states: States,
+    pub color_warning: Option<Color>,
+    pub color_critical: Option<Color>,
+    /// Flash the value while critical
+    #[serde(default)]
+    pub blink: bool,
+    #[serde(flatten)]
+    pub actions: Actions,
+    /// Everything else is up to the kind of module
+    #[serde(flatten)]
+    pub options: toml::Table,
 }
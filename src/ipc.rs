@@ -0,0 +1,147 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc,
+};
+
+/// Commands accepted on the socket, one per line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Run a module action, e.g. `action clock cycle-format`
+    Action {
+        module: String,
+        action: String,
+    },
+    /// Values of every module as JSON, like `pagbar dump --json`
+    Dump,
+    /// Reveal autohiding bars until the pointer leaves them
+    Show,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = match (words.next(), words.next(), words.next()) {
+            (Some("action"), Some(module), Some(action)) => Command::Action {
+                module: module.into(),
+                action: action.into(),
+            },
+            (Some("dump"), None, None) => Command::Dump,
+            (Some("show"), None, None) => Command::Show,
+            (Some("quit"), None, None) => Command::Quit,
+            _ => return Err(format!("unknown command \"{}\"", s.trim())),
+        };
+
+        match words.next() {
+            Some(_) => Err(format!("unexpected arguments in \"{}\"", s.trim())),
+            None => Ok(command),
+        }
+    }
+}
+
+/// A command together with where its answer goes
+pub struct Request {
+    pub command: Command,
+    pub reply: mpsc::Sender<Result<String, String>>,
+}
+
+pub fn socket_path() -> PathBuf {
+    xdg::BaseDirectories::new()
+        .ok()
+        .and_then(|dirs| dirs.place_runtime_file("pagbar.sock").ok())
+        .unwrap_or_else(|| std::env::temp_dir().join("pagbar.sock"))
+}
+
+/// The socket this instance listens on, removed when dropped
+pub struct Socket {
+    path: PathBuf,
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Accept commands on the socket in the background, `forward`
+/// hands them to whoever owns the bars
+pub fn listen(forward: impl Fn(Request) -> bool + Clone + Send + 'static) -> Option<Socket> {
+    let path = socket_path();
+    if UnixStream::connect(&path).is_ok() {
+        log::warn!("another pagbar is listening on {path:?}, ipc disabled");
+        return None;
+    }
+    // left behind by an instance that didn't shut down cleanly
+    let _ = std::fs::remove_file(&path);
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("unable to listen on {path:?}: {err}");
+            return None;
+        }
+    };
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // a client that never writes mustn't hold up the others
+            let forward = forward.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = serve(stream, &forward) {
+                    log::warn!("ipc client: {err}");
+                }
+            });
+        }
+    });
+    Some(Socket { path })
+}
+
+fn serve(stream: UnixStream, forward: &impl Fn(Request) -> bool) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let reply = match line.parse::<Command>() {
+            Ok(command) => {
+                let (reply, answer) = mpsc::channel();
+                if !forward(Request { command, reply }) {
+                    return Ok(());
+                }
+                answer
+                    .recv()
+                    .unwrap_or_else(|_| Err("bar shut down".into()))
+            }
+            Err(err) => Err(err),
+        };
+
+        match reply {
+            Ok(answer) => writeln!(writer, "ok {answer}")?,
+            Err(err) => writeln!(writer, "error {err}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Send a command to the running bar and wait for its answer
+pub fn send(command: &str) -> Result<String, String> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|err| format!("unable to connect to {path:?}: {err}"))?;
+    writeln!(stream, "{}", command.trim()).map_err(|err| err.to_string())?;
+
+    let mut answer = String::new();
+    BufReader::new(stream)
+        .read_line(&mut answer)
+        .map_err(|err| err.to_string())?;
+
+    match answer.trim_end().split_once(' ') {
+        Some(("ok", answer)) => Ok(answer.into()),
+        Some(("error", err)) => Err(err.into()),
+        _ if answer.trim_end() == "ok" => Ok(String::new()),
+        _ => Err(format!("malformed answer \"{}\"", answer.trim_end())),
+    }
+}
@@ -1,19 +1,72 @@
 use super::Bar;
+use crate::module::{Slot, SlotStatus};
 
 pub trait Layout {
     fn display(&mut self, ctx: &egui::Context, bar: &Bar);
+
+    /// Run an action on every module named `module`
+    fn action(&mut self, module: &str, _action: &str) -> Result<(), String> {
+        Err(format!("no module named \"{module}\""))
+    }
+
+    /// Draw the popup of the module named `module`
+    fn popup(&mut self, _ui: &mut egui::Ui, _bar: &Bar, _module: &str) {}
+
+    /// Update every module without drawing, for layouts that aren't shown
+    fn sample(&mut self, _ctx: &egui::Context, _bar: &Bar) {}
+
+    /// What every module showed last
+    fn status(&mut self) -> Vec<SlotStatus> {
+        Vec::new()
+    }
 }
 
-pub mod preset {
+/// Run an action on every slot named `module`
+pub fn slots_action<'a>(
+    slots: impl Iterator<Item = &'a mut Slot>,
+    module: &str,
+    action: &str,
+) -> Result<(), String> {
+    let mut slots = slots.filter(|slot| slot.config.name == module).peekable();
+    if slots.peek().is_none() {
+        return Err(format!("no module named \"{module}\""));
+    }
 
-    use systemstat::Platform;
+    // every slot has to run the action, don't short circuit
+    let mut handled = false;
+    for slot in slots {
+        handled |= slot.action(action);
+    }
+    if handled {
+        Ok(())
+    } else {
+        Err(format!("module \"{module}\" has no action \"{action}\""))
+    }
+}
+
+/// Draw the popup of the first slot named `module`
+pub fn slot_popup<'a>(
+    mut slots: impl Iterator<Item = &'a mut Slot>,
+    ui: &mut egui::Ui,
+    bar: &Bar,
+    module: &str,
+) {
+    if let Some(slot) = slots.find(|slot| slot.config.name == module) {
+        slot.popup(ui, bar);
+    }
+}
+
+pub mod preset {
 
     use super::Layout;
     use crate::bar::Bar;
+    use crate::module::{Panel, Slot, SlotStatus};
     use crate::Position;
 
     pub struct ThreeSplit {
-        sys: systemstat::System,
+        first: Vec<Slot>,
+        center: Vec<Slot>,
+        last: Vec<Slot>,
     }
 
     impl Layout for ThreeSplit {
@@ -53,40 +106,55 @@ pub mod preset {
                     .show(ctx, |ui| self.last(bar, ctx, ui));
             }
         }
-    }
 
-    impl Default for ThreeSplit {
-        fn default() -> Self {
-            Self {
-                sys: systemstat::System::new(),
+        fn action(&mut self, module: &str, action: &str) -> Result<(), String> {
+            super::slots_action(self.slots(), module, action)
+        }
+
+        fn popup(&mut self, ui: &mut egui::Ui, bar: &Bar, module: &str) {
+            super::slot_popup(self.slots(), ui, bar, module);
+        }
+
+        fn sample(&mut self, ctx: &egui::Context, bar: &Bar) {
+            for slot in self.slots() {
+                slot.sample(ctx, bar);
             }
         }
+
+        fn status(&mut self) -> Vec<SlotStatus> {
+            self.slots().map(|slot| slot.status()).collect()
+        }
     }
 
     impl ThreeSplit {
-        fn last(&mut self, options: &Bar, _ctx: &egui::Context, ui: &mut egui::Ui) {
-            use egui::*;
-            let stats = |ui: &mut Ui| {
-                let memory = match self.sys.memory() {
-                    Ok(mem) => (1. - mem.free.as_u64() as f64 / mem.total.as_u64() as f64) * 100.,
-                    Err(_) => 0.,
-                };
-
-                let disk = match self.sys.mount_at("/") {
-                    Ok(mount) => {
-                        (1. - mount.free.as_u64() as f64 / mount.total.as_u64() as f64) * 100.
-                    }
-                    Err(_) => 0.,
-                };
+        pub fn new(slots: Vec<Slot>) -> Self {
+            let (mut first, mut center, mut last) = (Vec::new(), Vec::new(), Vec::new());
+            for slot in slots {
+                match slot.config.panel {
+                    Panel::First => first.push(slot),
+                    Panel::Center => center.push(slot),
+                    Panel::Last => last.push(slot),
+                }
+            }
+            Self {
+                first,
+                center,
+                last,
+            }
+        }
 
-                let temp = self.sys.cpu_temp().unwrap_or(0.);
+        fn slots(&mut self) -> impl Iterator<Item = &mut Slot> {
+            self.first
+                .iter_mut()
+                .chain(self.center.iter_mut())
+                .chain(self.last.iter_mut())
+        }
 
-                ui.heading(RichText::new("/ ".to_string()).color(options.text));
-                ui.heading(RichText::new(format!("{disk:.0}%")).color(options.text_secondary));
-                ui.heading(RichText::new("ram ".to_string()).color(options.text));
-                ui.heading(RichText::new(format!("{memory:.0}%")).color(options.text_secondary));
-                ui.heading(RichText::new("cpu".to_string()).color(options.text));
-                ui.heading(RichText::new(format!("{temp}°C")).color(options.text_secondary));
+        fn last(&mut self, options: &Bar, ctx: &egui::Context, ui: &mut egui::Ui) {
+            let stats = |ui: &mut egui::Ui| {
+                for slot in self.last.iter_mut() {
+                    slot.show(ctx, ui, options);
+                }
                 ui.add_space(10.);
             };
 
@@ -97,22 +165,59 @@ pub mod preset {
             }
         }
 
-        fn first(&mut self, _cfg: &Bar, _ctx: &egui::Context, _ui: &mut egui::Ui) {}
+        fn first(&mut self, cfg: &Bar, ctx: &egui::Context, ui: &mut egui::Ui) {
+            let modules = |ui: &mut egui::Ui| {
+                ui.add_space(10.);
+                for slot in self.first.iter_mut() {
+                    slot.show(ctx, ui, cfg);
+                }
+            };
+
+            if matches!(cfg.position, Position::Bottom | Position::Top) {
+                ui.horizontal_centered(modules);
+            } else {
+                ui.vertical_centered(modules);
+            }
+        }
 
         fn center(&mut self, cfg: &Bar, ctx: &egui::Context, ui: &mut egui::Ui) {
             use egui::*;
-            ui.centered_and_justified(|ui| {
-                let date = chrono::Local::now();
-                ctx.request_repaint_after(std::time::Duration::from_secs(1));
+            // egui lays widgets out in a single pass, so the panel is
+            // centered using the extent its modules took last frame
+            let id = ui.id().with("center-extent");
+            let horizontal = matches!(cfg.position, Position::Bottom | Position::Top);
+            let extent = ui.data(|data| data.get_temp::<f32>(id)).unwrap_or(0.);
+
+            let modules = |ui: &mut Ui| {
+                let available = if horizontal {
+                    ui.available_width()
+                } else {
+                    ui.available_height()
+                };
+                ui.add_space(((available - extent) / 2.).max(0.));
+
+                let start = ui.cursor().min;
+                for slot in self.center.iter_mut() {
+                    slot.show(ctx, ui, cfg);
+                }
+                let end = ui.min_rect().max;
 
-                let date = if let Position::Bottom | Position::Top = cfg.position {
-                    date.format("%H:%M:%S").to_string()
+                let new_extent = if horizontal {
+                    end.x - start.x
                 } else {
-                    date.format("%H\n:%M:\n%S").to_string()
+                    end.y - start.y
                 };
+                if (new_extent - extent).abs() > 0.5 {
+                    ui.data_mut(|data| data.insert_temp(id, new_extent));
+                    ctx.request_repaint();
+                }
+            };
 
-                ui.heading(RichText::new(date).size(25.).color(cfg.text));
-            });
+            if horizontal {
+                ui.horizontal_centered(modules);
+            } else {
+                ui.vertical_centered(modules);
+            }
         }
     }
 }
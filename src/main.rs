@@ -1,35 +1,98 @@
-use std::time::Duration;
-use systemstat::Platform;
-
 use bar::{Bar, Position};
 
 use clap::Parser;
 
 mod bar;
+mod ipc;
 mod layout;
+mod module;
+#[cfg(feature = "native-plugins")]
+mod plugin;
+mod popup;
 
 #[derive(Parser)]
 struct Cli {
     #[arg(short, long, value_name = "FILE")]
     config: Option<std::path::PathBuf>,
+    /// Show lines read from stdin in lemonbar's format instead of the
+    /// configured modules, the config still sets up the bars
+    #[arg(long)]
+    stdin: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Send a command to the running bar, e.g. `msg action clock cycle-format`
+    Msg { command: Vec<String> },
+    /// Sample every module once and print its values, without opening a bar
+    Dump {
+        #[arg(long)]
+        json: bool,
+    },
 }
 
+/// Let modules that sample over time or run commands in the
+/// background produce a value before `dump` prints them
+const DUMP_SETTLE: std::time::Duration = std::time::Duration::from_millis(500);
+
 fn main() {
     env_logger::init();
     let args = Cli::parse();
 
-    if args.config.is_none() {
+    if let Some(Command::Msg { command }) = &args.command {
+        match ipc::send(&command.join(" ")) {
+            Ok(answer) if answer.is_empty() => {}
+            Ok(answer) => println!("{answer}"),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let path = args.config.unwrap_or_else(|| {
         //LOOK FOR CONFIG IN XDG_CONFIG_HOME
         let base_dirs = xdg::BaseDirectories::new().unwrap();
-        let config = bar::from_path(
-            base_dirs.get_config_home().join("pagbar/config.toml"),
-            || Box::new(layout::preset::ThreeSplit::default()),
-        );
-        bar::run(bar::Protocol::X11, config);
+        base_dirs.get_config_home().join("pagbar/config.toml")
+    });
+    let mut config = bar::from_path(path, |slots| {
+        Box::new(layout::preset::ThreeSplit::new(slots))
+    });
+    if let Some(Command::Dump { json }) = args.command {
+        dump(&config, json);
+        return;
+    }
+    if args.stdin {
+        for (bar, _) in &mut config {
+            bar.modules = module::ModuleConfig::stdin();
+            bar.layout = None;
+        }
+    }
+    bar::run(bar::Protocol::X11, config);
+}
+
+fn dump(config: &[(Bar, bar::LayoutFactory)], json: bool) {
+    let Some((bar, layout_factory)) = config.first() else {
+        eprintln!("no bars are configured");
+        std::process::exit(1);
+    };
+
+    let ctx = egui::Context::default();
+    ctx.set_fonts((*bar.fonts).clone());
+    let mut layout = bar.create_layout(*layout_factory);
+    layout.sample(&ctx, bar);
+    std::thread::sleep(DUMP_SETTLE);
+    layout.sample(&ctx, bar);
+    let modules = layout.status();
+
+    if json {
+        println!("{}", serde_json::to_string(&modules).unwrap());
     } else {
-        let options = bar::from_path(args.config.unwrap(), || {
-            Box::new(layout::preset::ThreeSplit::default())
-        });
-        bar::run(bar::Protocol::X11, options);
+        for module in modules {
+            println!("{}: {}", module.name, module.text);
+        }
     }
 }
@@ -0,0 +1,816 @@
+use egui::{text::LayoutJob, Color32, FontFamily, FontId, TextFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bar::{Bar, Color, Position},
+    popup::{self, Menu, Tooltip},
+};
+
+pub use action::{Action, Actions};
+
+mod action;
+mod clock;
+mod disk;
+mod exec;
+pub mod format;
+mod i3bar;
+mod keyboard;
+mod lemonbar;
+#[cfg(feature = "lua")]
+mod lua;
+pub mod markup;
+mod memory;
+mod temperature;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+/// A single readout on the bar
+pub trait Module {
+    /// Sample the current value, called every time the bar is drawn
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content;
+
+    /// Handle an action by name, e.g. `cycle-format`,
+    /// returns false for actions the module doesn't know
+    fn on_action(&mut self, _action: &str) -> bool {
+        false
+    }
+
+    /// Handle a click or scroll on a tagged part of the module's content,
+    /// returns false to run the configured action instead
+    fn on_click(&mut self, _click: Click) -> bool {
+        false
+    }
+
+    /// Extended information shown while the module is hovered
+    fn tooltip(&mut self, _bar: &Bar) -> Option<Content> {
+        None
+    }
+
+    /// Whether the module has a popup, opened by clicking it
+    fn has_popup(&self) -> bool {
+        false
+    }
+
+    /// Draw the popup, called every frame while it is open
+    fn popup(&mut self, _ui: &mut egui::Ui, _bar: &Bar) {}
+
+    /// Action a left click runs when `on-click-left` isn't set
+    fn click_action(&self) -> Option<&str> {
+        self.has_popup().then_some(TOGGLE_POPUP)
+    }
+
+    /// Reading from the last update that `states` are compared
+    /// against, modules without a number return None
+    fn level(&self) -> Option<f32> {
+        None
+    }
+
+    /// Thresholds used for the ones missing from `states`
+    fn default_states(&self) -> States {
+        States::default()
+    }
+}
+
+/// A click on a part of a module, see `Content::tagged`
+#[derive(Debug, Clone, Copy)]
+pub struct Click {
+    pub tag: usize,
+    /// X11 numbering, 1 to 3 are left, middle and right, 4 and 5 scroll
+    pub button: u8,
+    /// Pointer position relative to the part
+    pub pos: egui::Vec2,
+    pub size: egui::Vec2,
+    /// Pointer position in the bar's window
+    pub window_pos: egui::Pos2,
+}
+
+/// Module kinds that can be used in `[module.<name>]`
+pub const KINDS: &[&str] = &[
+    "clock",
+    "disk",
+    "ram",
+    "cpu",
+    "exec",
+    "i3bar",
+    "keyboard",
+    #[cfg(feature = "lua")]
+    "lua",
+    #[cfg(feature = "wasm")]
+    "wasm",
+];
+
+/// Builtin kinds and the ones added by plugins
+pub fn kinds() -> Vec<String> {
+    let kinds = KINDS.iter().map(|kind| kind.to_string());
+    #[cfg(feature = "native-plugins")]
+    let kinds = kinds.chain(crate::plugin::kinds());
+    kinds.collect()
+}
+
+/// Instantiate a module of `kind` from the rest of its config section
+pub fn create(kind: &str, options: &toml::Table) -> Result<Box<dyn Module>, String> {
+    Ok(match kind {
+        "clock" => Box::new(clock::Clock::new(parse_options(options)?)?),
+        "disk" => Box::new(disk::Disk::new(parse_options(options)?)?),
+        "ram" => Box::new(memory::Memory::new(parse_options(options)?)?),
+        "cpu" => Box::new(temperature::Temperature::new(parse_options(options)?)?),
+        "exec" => Box::new(exec::Exec::new(parse_options(options)?)?),
+        "i3bar" => Box::new(i3bar::I3bar::new(parse_options(options)?)?),
+        "keyboard" => Box::new(keyboard::Keyboard::new(parse_options(options)?)?),
+        // Not in KINDS, only used by `--stdin`
+        "lemonbar" => Box::new(lemonbar::Lemonbar::new(parse_options(options)?)?),
+        #[cfg(feature = "lua")]
+        "lua" => Box::new(lua::LuaModule::new(parse_options(options)?)?),
+        #[cfg(feature = "wasm")]
+        "wasm" => Box::new(wasm::WasmModule::new(parse_options(options)?)?),
+        _ => {
+            #[cfg(feature = "native-plugins")]
+            if let Some(module) = crate::plugin::create_module(kind, options) {
+                return module;
+            }
+            return Err(format!("unknown module kind \"{kind}\""));
+        }
+    })
+}
+
+fn parse_options<T: serde::de::DeserializeOwned>(options: &toml::Table) -> Result<T, String> {
+    toml::Value::Table(options.clone())
+        .try_into()
+        .map_err(|err| err.to_string().trim_end().to_string())
+}
+
+/// Expand `~` in a path from the config, relative paths are joined to `base`
+#[cfg(any(feature = "lua", feature = "wasm"))]
+fn user_path(path: &std::path::Path, base: Option<std::path::PathBuf>) -> std::path::PathBuf {
+    let path = match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => std::path::Path::new(&home).join(rest),
+        _ => path.to_path_buf(),
+    };
+    match base {
+        Some(base) if path.is_relative() => base.join(path),
+        _ => path,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Panel {
+    First,
+    #[serde(alias = "middle")]
+    Center,
+    Last,
+}
+
+/// Thresholds a module's level switches to warning or critical at
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct States {
+    pub warning: Option<f32>,
+    pub critical: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl States {
+    /// Fill thresholds that aren't set from `defaults`
+    pub fn or(self, defaults: States) -> States {
+        States {
+            warning: self.warning.or(defaults.warning),
+            critical: self.critical.or(defaults.critical),
+        }
+    }
+
+    pub fn state(&self, level: f32) -> State {
+        if self.critical.is_some_and(|critical| level >= critical) {
+            State::Critical
+        } else if self.warning.is_some_and(|warning| level >= warning) {
+            State::Warning
+        } else {
+            State::Normal
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleConfig {
+    pub name: String,
+    pub kind: String,
+    pub panel: Panel,
+    pub font: FontId,
+    pub actions: Actions,
+    pub states: States,
+    /// Override the bar's warning and critical colors
+    pub color_warning: Option<Color>,
+    pub color_critical: Option<Color>,
+    pub blink: bool,
+    /// Options of the module kind, e.g. `format` for clocks
+    pub options: toml::Table,
+}
+
+impl ModuleConfig {
+    /// Modules shown when the config doesn't list any
+    pub fn defaults() -> Vec<Self> {
+        let heading = FontId::new(18., FontFamily::Proportional);
+        let module = |kind: &str, panel, font: &FontId| ModuleConfig {
+            name: kind.into(),
+            kind: kind.into(),
+            panel,
+            font: font.clone(),
+            actions: Actions::default(),
+            states: States::default(),
+            color_warning: None,
+            color_critical: None,
+            blink: false,
+            options: toml::Table::new(),
+        };
+
+        vec![
+            module("disk", Panel::Last, &heading),
+            module("ram", Panel::Last, &heading),
+            module("cpu", Panel::Last, &heading),
+            module(
+                "clock",
+                Panel::Center,
+                &FontId::new(25., FontFamily::Proportional),
+            ),
+        ]
+    }
+
+    /// A module per panel showing the sections of lines read from stdin
+    pub fn stdin() -> Vec<Self> {
+        [Panel::First, Panel::Center, Panel::Last]
+            .into_iter()
+            .map(|panel| {
+                let mut options = toml::Table::new();
+                options.insert(
+                    "panel".into(),
+                    toml::Value::try_from(panel).expect("panel serializes"),
+                );
+                ModuleConfig {
+                    name: "stdin".into(),
+                    kind: "lemonbar".into(),
+                    panel,
+                    font: FontId::new(18., FontFamily::Proportional),
+                    actions: Actions::default(),
+                    states: States::default(),
+                    color_warning: None,
+                    color_critical: None,
+                    blink: false,
+                    options,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Values of a module, as printed by `pagbar dump`
+#[derive(Debug, Clone, Serialize)]
+pub struct SlotStatus {
+    pub name: String,
+    pub kind: String,
+    pub panel: Panel,
+    pub text: String,
+    pub level: Option<f32>,
+    pub state: State,
+}
+
+/// Action every slot understands, opens or closes the module's popup
+const TOGGLE_POPUP: &str = "toggle-popup";
+
+/// How long each phase of a blinking value lasts, in seconds
+const BLINK_INTERVAL: f64 = 0.5;
+
+/// How often modules reading system stats sample them, the bar only
+/// redraws when something asks for it
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Tooltips keep the module's font family, at a size that fits more lines
+const TOOLTIP_SIZE: f32 = 15.;
+
+/// Left in a slot whose module couldn't be created
+struct Blank;
+
+impl Module for Blank {
+    fn update(&mut self, _ctx: &egui::Context, _bar: &Bar) -> Content {
+        Content::default()
+    }
+}
+
+/// A module instance placed in a panel of the layout
+pub struct Slot {
+    pub config: ModuleConfig,
+    module: Box<dyn Module>,
+    hovered: bool,
+    popup_open: bool,
+    /// Set when the popup was opened, it takes over the bar's menu next frame
+    popup_claim: bool,
+    /// Text of the last update, for `pagbar dump`
+    text: String,
+}
+
+impl Slot {
+    pub fn new(config: &ModuleConfig) -> Self {
+        // options were checked when the config was loaded, this fails when
+        // e.g. a plugin changed since, which shouldn't take the bar down
+        let module = create(&config.kind, &config.options).unwrap_or_else(|err| {
+            log::warn!("[module.{}]: {err}", config.name);
+            Box::new(Blank)
+        });
+        Self {
+            config: config.clone(),
+            module,
+            hovered: false,
+            popup_open: false,
+            popup_claim: false,
+            text: String::new(),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, ui: &mut egui::Ui, bar: &Bar) -> egui::Response {
+        let mut content = self.module.update(ctx, bar);
+        self.text = content.text();
+        if let Some(color) = self.state_color(ctx, bar) {
+            content.recolor_values(color);
+        }
+        let halign = match bar.position {
+            Position::Left | Position::Right => egui::Align::Center,
+            Position::Top | Position::Bottom => egui::Align::LEFT,
+        };
+        content.fit(ctx, &self.config.font);
+        let response = ui.add(
+            egui::Label::new(content.layout_job(&self.config.font, halign))
+                .sense(egui::Sense::click()),
+        );
+        self.interact(ui, &response, &content, halign);
+
+        if self.popup_open {
+            if std::mem::take(&mut self.popup_claim) {
+                popup::claim_menu(ctx, &self.config.name);
+            } else if !popup::owns_menu(ctx, &self.config.name) {
+                self.popup_open = false;
+            }
+        }
+
+        if self.popup_open {
+            popup::request_menu(
+                ctx,
+                Menu {
+                    module: self.config.name.clone(),
+                    anchor: response.rect,
+                },
+            );
+        } else if response.hovered() {
+            if let Some(content) = self.module.tooltip(bar) {
+                popup::request_tooltip(
+                    ctx,
+                    Tooltip {
+                        anchor: response.rect,
+                        font: FontId::new(TOOLTIP_SIZE, self.config.font.family.clone()),
+                        content,
+                    },
+                );
+            }
+        }
+        response
+    }
+
+    /// Update the module without drawing it
+    pub fn sample(&mut self, ctx: &egui::Context, bar: &Bar) {
+        self.text = self.module.update(ctx, bar).text();
+    }
+
+    /// What the slot showed last
+    pub fn status(&self) -> SlotStatus {
+        let level = self.module.level();
+        let states = self.config.states.or(self.module.default_states());
+        SlotStatus {
+            name: self.config.name.clone(),
+            kind: self.config.kind.clone(),
+            panel: self.config.panel,
+            text: self.text.clone(),
+            level,
+            state: level.map_or(State::Normal, |level| states.state(level)),
+        }
+    }
+
+    pub fn action(&mut self, action: &str) -> bool {
+        if action == TOGGLE_POPUP && self.module.has_popup() {
+            self.popup_open = !self.popup_open;
+            self.popup_claim = self.popup_open;
+            return true;
+        }
+        self.module.on_action(action)
+    }
+
+    /// Color of the module's values for its current state, if not normal
+    fn state_color(&self, ctx: &egui::Context, bar: &Bar) -> Option<Color32> {
+        let level = self.module.level()?;
+        let states = self.config.states.or(self.module.default_states());
+
+        match states.state(level) {
+            State::Normal => None,
+            State::Warning => Some(self.config.color_warning.unwrap_or(bar.warning).into()),
+            State::Critical if self.config.blink => {
+                ctx.request_repaint_after(std::time::Duration::from_secs_f64(BLINK_INTERVAL));
+                let phase = (ctx.input(|input| input.time) / BLINK_INTERVAL) as u64;
+                phase
+                    .is_multiple_of(2)
+                    .then(|| self.config.color_critical.unwrap_or(bar.critical).into())
+            }
+            State::Critical => Some(self.config.color_critical.unwrap_or(bar.critical).into()),
+        }
+    }
+
+    pub fn popup(&mut self, ui: &mut egui::Ui, bar: &Bar) {
+        self.module.popup(ui, bar);
+    }
+
+    /// Lay the content out again to find the tagged part under the pointer
+    fn click(
+        &self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        content: &Content,
+        halign: egui::Align,
+        button: u8,
+    ) -> Option<Click> {
+        let window_pos = response.hover_pos()?;
+        let job = content.layout_job(&self.config.font, egui::Align::LEFT);
+        let galley = ui.fonts(|fonts| fonts.layout_job(job));
+        let origin = response.rect.left_top()
+            + egui::vec2(
+                match halign {
+                    egui::Align::Center => (response.rect.width() - galley.size().x) / 2.,
+                    _ => 0.,
+                },
+                0.,
+            );
+
+        let cursor = galley.cursor_from_pos(window_pos - origin);
+        let tag = content.tag_at(cursor.ccursor.index)?;
+        let chars = content.tag_chars(tag);
+        let pos = |index| {
+            let cursor = galley.from_ccursor(egui::text::CCursor::new(index));
+            galley.pos_from_cursor(&cursor)
+        };
+        let (start, end) = (pos(chars.start), pos(chars.end));
+        let rect = egui::Rect::from_min_max(start.min, end.max).translate(origin.to_vec2());
+
+        Some(Click {
+            tag,
+            button,
+            pos: window_pos - rect.min,
+            size: rect.size(),
+            window_pos,
+        })
+    }
+
+    fn interact(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        content: &Content,
+        halign: egui::Align,
+    ) {
+        let hovered = response.hovered();
+        let scroll = if hovered {
+            ui.input(|input| input.scroll_delta.y)
+        } else {
+            0.
+        };
+
+        let triggers = [
+            response.clicked(),
+            response.secondary_clicked(),
+            response.middle_clicked(),
+            scroll > 0.,
+            scroll < 0.,
+        ];
+        // Tagged parts get the click first, in X11 button numbering
+        let mut handled = [false; 5];
+        if content.has_tags() {
+            for (i, button) in [1, 3, 2, 4, 5].into_iter().enumerate() {
+                handled[i] = triggers[i]
+                    && self
+                        .click(ui, response, content, halign, button)
+                        .is_some_and(|click| self.module.on_click(click));
+            }
+        }
+        let triggered = |i: usize| triggers[i] && !handled[i];
+
+        let actions = &self.config.actions;
+        let click_action = self
+            .module
+            .click_action()
+            .map(|action| Action::Module(action.into()));
+        let fired = [
+            (
+                triggered(0),
+                actions.on_click_left.as_ref().or(click_action.as_ref()),
+            ),
+            (triggered(1), actions.on_click_right.as_ref()),
+            (triggered(2), actions.on_click_middle.as_ref()),
+            (triggered(3), actions.on_scroll_up.as_ref()),
+            (triggered(4), actions.on_scroll_down.as_ref()),
+            (hovered && !self.hovered, actions.on_hover.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(triggered, action)| action.filter(|_| triggered))
+        .cloned()
+        .collect::<Vec<_>>();
+        self.hovered = hovered;
+
+        for action in fired {
+            if let Some(action) = action.run() {
+                if !self.action(action) {
+                    log::warn!("module \"{}\" has no action \"{action}\"", self.config.name);
+                }
+                ui.ctx().request_repaint();
+            }
+        }
+    }
+}
+
+/// Text of a module, made of differently colored spans
+#[derive(Debug, Clone, Default)]
+pub struct Content {
+    spans: Vec<Span>,
+    /// Runs of spans widened to a minimum width by `fit`
+    paddings: Vec<Padding>,
+}
+
+#[derive(Debug, Clone)]
+struct Span {
+    text: String,
+    color: Color32,
+    /// Part of the reading rather than a label, recolored by `states`
+    value: bool,
+    /// Empty space before the text, in points
+    leading: f32,
+    /// Part of the module the span belongs to, passed to `Module::on_click`
+    tag: Option<usize>,
+    style: Style,
+}
+
+#[derive(Debug, Clone)]
+struct Padding {
+    spans: std::ops::Range<usize>,
+    width: MinWidth,
+    align: egui::Align,
+}
+
+/// Minimum width of a run of spans
+#[derive(Debug, Clone)]
+pub enum MinWidth {
+    Points(f32),
+    /// As wide as this text in the module's font
+    Text(String),
+}
+
+/// Styling of a span beyond its color, set by markup
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    background: Color32,
+    italics: bool,
+    underline: bool,
+    strikethrough: bool,
+    size: Size,
+    /// Family used instead of the module's, e.g. for bold text
+    family: Option<FontFamily>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Size {
+    /// The module's font size
+    #[default]
+    Inherit,
+    Points(f32),
+    /// Relative to the module's font size
+    Scale(f32),
+}
+
+impl Content {
+    pub fn push(mut self, text: impl Into<String>, color: impl Into<Color32>) -> Self {
+        self.spans.push(Span {
+            text: text.into(),
+            color: color.into(),
+            value: false,
+            leading: 0.,
+            tag: None,
+            style: Style::default(),
+        });
+        self
+    }
+
+    pub fn styled(
+        mut self,
+        text: impl Into<String>,
+        color: impl Into<Color32>,
+        style: Style,
+    ) -> Self {
+        self.spans.push(Span {
+            text: text.into(),
+            color: color.into(),
+            value: false,
+            leading: 0.,
+            tag: None,
+            style,
+        });
+        self
+    }
+
+    /// Push a span that shows the module's reading
+    pub fn value(mut self, text: impl Into<String>, color: impl Into<Color32>) -> Self {
+        self.spans.push(Span {
+            text: text.into(),
+            color: color.into(),
+            value: true,
+            leading: 0.,
+            tag: None,
+            style: Style::default(),
+        });
+        self
+    }
+
+    /// Push empty space, e.g. between the parts of a module
+    pub fn space(mut self, points: f32) -> Self {
+        self.spans.push(Span {
+            text: String::new(),
+            color: Color32::TRANSPARENT,
+            value: false,
+            leading: points,
+            tag: None,
+            style: Style::default(),
+        });
+        self
+    }
+
+    /// Append `inner` with its spans tagged as part `tag` of the module
+    pub fn tagged(mut self, tag: usize, mut inner: Content) -> Self {
+        for span in &mut inner.spans {
+            span.tag = Some(tag);
+        }
+        self.extend(inner);
+        self
+    }
+
+    /// Append `inner`, padded to at least `width` when the bar draws it
+    pub fn padded(mut self, inner: Content, width: MinWidth, align: egui::Align) -> Self {
+        let start = self.spans.len();
+        self.paddings.push(Padding {
+            spans: start..start + inner.spans.len(),
+            width,
+            align,
+        });
+        self.extend(inner);
+        self
+    }
+
+    pub fn extend(&mut self, other: Content) {
+        let offset = self.spans.len();
+        self.paddings
+            .extend(other.paddings.into_iter().map(|padding| Padding {
+                spans: padding.spans.start + offset..padding.spans.end + offset,
+                ..padding
+            }));
+        self.spans.extend(other.spans);
+    }
+
+    /// Text of the spans, empty space becomes a blank
+    pub fn text(&self) -> String {
+        self.spans
+            .iter()
+            .map(|span| match span.text.as_str() {
+                "" if span.leading > 0. => " ",
+                text => text,
+            })
+            .collect()
+    }
+
+    pub fn has_tags(&self) -> bool {
+        self.spans.iter().any(|span| span.tag.is_some())
+    }
+
+    /// Tag of the span the `index`th character is in
+    pub fn tag_at(&self, index: usize) -> Option<usize> {
+        let mut end = 0;
+        self.spans.iter().find_map(|span| {
+            end += span.text.chars().count();
+            (index < end).then_some(span.tag)
+        })?
+    }
+
+    /// Range of characters the spans tagged `tag` cover
+    fn tag_chars(&self, tag: usize) -> std::ops::Range<usize> {
+        let (mut start, mut end, mut chars) = (None, 0, 0);
+        for span in &self.spans {
+            let len = span.text.chars().count();
+            if span.tag == Some(tag) {
+                start.get_or_insert(chars);
+                end = chars + len;
+            }
+            chars += len;
+        }
+        start.unwrap_or(0)..end
+    }
+
+    /// Widen padded runs of spans to their minimum width
+    pub fn fit(&mut self, ctx: &egui::Context, font: &FontId) {
+        let measure = |spans: Vec<Span>| {
+            let content = Content {
+                spans,
+                paddings: Vec::new(),
+            };
+            let job = content.layout_job(font, egui::Align::LEFT);
+            ctx.fonts(|fonts| fonts.layout_job(job)).size().x
+        };
+
+        for padding in std::mem::take(&mut self.paddings) {
+            let range = padding.spans;
+            if range.is_empty() {
+                continue;
+            }
+            let min = match padding.width {
+                MinWidth::Points(points) => points,
+                MinWidth::Text(text) => {
+                    measure(Content::default().push(text, Color32::TRANSPARENT).spans)
+                }
+            };
+            let extra = min - measure(self.spans[range.clone()].to_vec());
+            if extra <= 0. {
+                continue;
+            }
+
+            let (before, after) = match padding.align {
+                egui::Align::Min => (0., extra),
+                egui::Align::Center => (extra / 2., extra / 2.),
+                egui::Align::Max => (extra, 0.),
+            };
+            self.spans[range.start].leading += before;
+            if after <= 0. {
+                continue;
+            }
+            if let Some(next) = self.spans.get_mut(range.end) {
+                next.leading += after;
+            } else {
+                // Space before an empty span at the end isn't counted
+                // towards the width, so pad a blank instead
+                let mut blank = Content::default().push(" ", Color32::TRANSPARENT).spans;
+                blank[0].leading = (after - measure(blank.clone())).max(0.);
+                blank[0].tag = self.spans[range.end - 1].tag;
+                self.spans.extend(blank);
+            }
+        }
+    }
+
+    pub fn recolor_values(&mut self, color: Color32) {
+        for span in self.spans.iter_mut().filter(|span| span.value) {
+            span.color = color;
+        }
+    }
+
+    pub fn layout_job(&self, font: &FontId, halign: egui::Align) -> LayoutJob {
+        let mut job = LayoutJob {
+            halign,
+            ..Default::default()
+        };
+
+        for span in &self.spans {
+            let style = &span.style;
+            let size = match style.size {
+                Size::Inherit => font.size,
+                Size::Points(points) => points,
+                Size::Scale(scale) => font.size * scale,
+            };
+            let stroke = egui::Stroke::new(1., span.color);
+            job.append(
+                &span.text,
+                span.leading,
+                TextFormat {
+                    font_id: FontId::new(size, style.family.clone().unwrap_or(font.family.clone())),
+                    color: span.color,
+                    background: style.background,
+                    italics: style.italics,
+                    underline: if style.underline {
+                        stroke
+                    } else {
+                        egui::Stroke::NONE
+                    },
+                    strikethrough: if style.strikethrough {
+                        stroke
+                    } else {
+                        egui::Stroke::NONE
+                    },
+                    ..Default::default()
+                },
+            );
+        }
+        job
+    }
+}
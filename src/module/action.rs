@@ -0,0 +1,128 @@
+use std::process::Stdio;
+
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Serialize,
+};
+
+/// What happens when a module is clicked, scrolled or hovered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Shell command, `on-click-left = "pavucontrol"`
+    Exec(String),
+    /// Command for the bar's ipc socket, `{ ipc = "action clock cycle-format" }`
+    Ipc(String),
+    /// Action handled by the module itself, `{ action = "cycle-format" }`
+    Module(String),
+}
+
+impl Action {
+    /// Run actions that don't concern the module, module actions are handed back
+    pub fn run(&self) -> Option<&str> {
+        match self {
+            Action::Exec(command) => exec(command),
+            Action::Ipc(command) => {
+                let command = command.clone();
+                std::thread::spawn(move || {
+                    if let Err(err) = crate::ipc::send(&command) {
+                        log::warn!("ipc action \"{command}\" failed: {err}");
+                    }
+                });
+            }
+            Action::Module(action) => return Some(action),
+        }
+        None
+    }
+}
+
+/// Spawn a shell command detached from the bar, reaping it once it exits
+pub fn exec(command: &str) {
+    let child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .spawn();
+
+    match child {
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(err) => log::warn!("unable to run \"{command}\": {err}"),
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Actions {
+    pub on_click_left: Option<Action>,
+    pub on_click_right: Option<Action>,
+    pub on_click_middle: Option<Action>,
+    pub on_scroll_up: Option<Action>,
+    pub on_scroll_down: Option<Action>,
+    pub on_hover: Option<Action>,
+}
+
+impl Serialize for Action {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+        let (key, value) = match self {
+            Action::Exec(command) => return serializer.serialize_str(command),
+            Action::Ipc(command) => ("ipc", command),
+            Action::Module(action) => ("action", action),
+        };
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(key, value)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Action {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ActionVisitor)
+    }
+}
+
+struct ActionVisitor;
+
+impl<'de> Visitor<'de> for ActionVisitor {
+    type Value = Action;
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Action::Exec(v.into()))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        use serde::de::Error;
+        let Some((key, value)) = map.next_entry::<String, String>()? else {
+            return Err(A::Error::custom("empty action"));
+        };
+        if map.next_key::<String>()?.is_some() {
+            return Err(A::Error::custom("an action takes exactly one key"));
+        }
+
+        match key.as_str() {
+            "exec" => Ok(Action::Exec(value)),
+            "ipc" => Ok(Action::Ipc(value)),
+            "action" => Ok(Action::Module(value)),
+            key => Err(A::Error::custom(format!(
+                "unknown action \"{key}\", expected exec, ipc or action"
+            ))),
+        }
+    }
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("shell command or { exec | ipc | action = \"...\" }")
+    }
+}
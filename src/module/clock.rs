@@ -0,0 +1,252 @@
+use std::{fmt::Display, time::Duration};
+
+use chrono::{
+    format::{Fixed, Item, Numeric, StrftimeItems},
+    DateTime, Datelike, Local, Months, NaiveDate, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use super::{Content, Module, TOGGLE_POPUP};
+use crate::bar::{Bar, Position};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ClockConfig {
+    /// strftime format, `%H:%M:%S` by default
+    format: Option<String>,
+    /// Used on left and right bars, defaults to `format` if that is set
+    format_vertical: Option<String>,
+    /// Shown instead of `format` after a click
+    format_alt: Option<String>,
+    format_alt_vertical: Option<String>,
+    /// IANA name like `Europe/Helsinki`, the local time zone by default
+    timezone: Option<Tz>,
+}
+
+/// A strftime format together with how often its output changes
+struct Format {
+    format: String,
+    precision: Precision,
+}
+
+/// Smallest unit of time a format shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precision {
+    Second,
+    Minute,
+    /// Also used by date only formats, days don't always last 24 hours
+    Hour,
+}
+
+impl Format {
+    fn new(format: String) -> Result<Self, String> {
+        let mut precision = Precision::Hour;
+        for item in StrftimeItems::new(&format) {
+            let item_precision = match item {
+                Item::Error => return Err(format!("invalid format \"{format}\"")),
+                Item::Numeric(Numeric::Second | Numeric::Nanosecond | Numeric::Timestamp, _)
+                | Item::Fixed(
+                    Fixed::Nanosecond
+                    | Fixed::Nanosecond3
+                    | Fixed::Nanosecond6
+                    | Fixed::Nanosecond9
+                    | Fixed::RFC2822
+                    | Fixed::RFC3339,
+                ) => Precision::Second,
+                Item::Numeric(Numeric::Minute, _) => Precision::Minute,
+                _ => Precision::Hour,
+            };
+            precision = precision.min(item_precision);
+        }
+        Ok(Self { format, precision })
+    }
+
+    /// Time until the formatted output changes next
+    fn until_next<T: TimeZone>(&self, now: &DateTime<T>) -> Duration {
+        let (period, elapsed) = match self.precision {
+            Precision::Second => (1, 0),
+            Precision::Minute => (60, now.second()),
+            Precision::Hour => (3600, now.minute() * 60 + now.second()),
+        };
+        // leap seconds are reported as nanoseconds past a full second
+        let nanos = now.nanosecond().min(999_999_999);
+        Duration::from_secs((period - elapsed) as u64) - Duration::from_nanos(nanos as u64)
+    }
+}
+
+pub struct Clock {
+    /// Horizontal and vertical variants of the main format
+    format: (Format, Format),
+    format_alt: (Format, Format),
+    /// Whether `format-alt` was configured, clicks toggle it if so
+    has_alt: bool,
+    /// Show the alternate format
+    alt: bool,
+    timezone: Option<Tz>,
+    /// Month shown in the calendar, relative to the current one
+    calendar_offset: i32,
+}
+
+impl Clock {
+    pub fn new(config: ClockConfig) -> Result<Self, String> {
+        let vertical = config.format_vertical.or(config.format.clone());
+        let alt_vertical = config.format_alt_vertical.or(config.format_alt.clone());
+        let has_alt = config.format_alt.is_some();
+
+        Ok(Self {
+            format: (
+                Format::new(config.format.unwrap_or("%H:%M:%S".into()))?,
+                Format::new(vertical.unwrap_or("%H\n:%M:\n%S".into()))?,
+            ),
+            format_alt: (
+                Format::new(config.format_alt.unwrap_or("%a %d %b".into()))?,
+                Format::new(alt_vertical.unwrap_or("%d\n%m".into()))?,
+            ),
+            has_alt,
+            alt: false,
+            timezone: config.timezone,
+            calendar_offset: 0,
+        })
+    }
+
+    fn format(&self, horizontal: bool) -> &Format {
+        let (horizontal_format, vertical_format) = if self.alt {
+            &self.format_alt
+        } else {
+            &self.format
+        };
+        if horizontal {
+            horizontal_format
+        } else {
+            vertical_format
+        }
+    }
+
+    /// Format the current time in the clock's time zone
+    fn now(&self, format: &Format) -> (String, Duration) {
+        fn render<T: TimeZone>(now: DateTime<T>, format: &Format) -> (String, Duration)
+        where
+            T::Offset: Display,
+        {
+            (
+                now.format(&format.format).to_string(),
+                format.until_next(&now),
+            )
+        }
+
+        match self.timezone {
+            Some(timezone) => render(Utc::now().with_timezone(&timezone), format),
+            None => render(Local::now(), format),
+        }
+    }
+
+    fn today(&self) -> NaiveDate {
+        match self.timezone {
+            Some(timezone) => Utc::now().with_timezone(&timezone).date_naive(),
+            None => Local::now().date_naive(),
+        }
+    }
+}
+
+impl Module for Clock {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        let horizontal = matches!(bar.position, Position::Bottom | Position::Top);
+        let (date, next) = self.now(self.format(horizontal));
+        ctx.request_repaint_after(next);
+
+        Content::default().push(date, bar.text)
+    }
+
+    fn tooltip(&mut self, bar: &Bar) -> Option<Content> {
+        let format = Format {
+            format: "%A, %d %B %Y".into(),
+            precision: Precision::Hour,
+        };
+        let (date, _) = self.now(&format);
+        Some(Content::default().push(date, bar.text))
+    }
+
+    fn on_action(&mut self, action: &str) -> bool {
+        match action {
+            "cycle-format" => self.alt = !self.alt,
+            _ => return false,
+        }
+        true
+    }
+
+    fn has_popup(&self) -> bool {
+        true
+    }
+
+    fn click_action(&self) -> Option<&str> {
+        Some(if self.has_alt {
+            "cycle-format"
+        } else {
+            TOGGLE_POPUP
+        })
+    }
+
+    fn popup(&mut self, ui: &mut egui::Ui, bar: &Bar) {
+        let today = self.today();
+        let first = today.with_day(1).unwrap();
+        let months = Months::new(self.calendar_offset.unsigned_abs());
+        let first = if self.calendar_offset < 0 {
+            first.checked_sub_months(months)
+        } else {
+            first.checked_add_months(months)
+        }
+        .unwrap_or(first);
+
+        ui.horizontal(|ui| {
+            if ui.button("<").clicked() {
+                self.calendar_offset -= 1;
+            }
+            let title = egui::RichText::new(first.format("%B %Y").to_string()).color(bar.text);
+            // clicking the month goes back to today
+            if ui
+                .add(egui::Label::new(title).sense(egui::Sense::click()))
+                .clicked()
+            {
+                self.calendar_offset = 0;
+            }
+            if ui.button(">").clicked() {
+                self.calendar_offset += 1;
+            }
+        });
+
+        egui::Grid::new("calendar")
+            .min_col_width(24.)
+            .show(ui, |ui| calendar(ui, bar, first, today));
+    }
+}
+
+/// Month view starting on monday, `first` is the first of the month
+fn calendar(ui: &mut egui::Ui, bar: &Bar, first: NaiveDate, today: NaiveDate) {
+    for weekday in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"] {
+        ui.label(egui::RichText::new(weekday).color(bar.text_secondary));
+    }
+    ui.end_row();
+
+    let leading = first.weekday().num_days_from_monday();
+    for _ in 0..leading {
+        ui.label("");
+    }
+
+    let days = first
+        .iter_days()
+        .take_while(|day| day.month() == first.month());
+    for (column, day) in (leading..).zip(days) {
+        let text = egui::RichText::new(day.day().to_string());
+        let text = if day == today {
+            text.color(bar.text).strong().underline()
+        } else {
+            text.color(bar.text)
+        };
+        ui.label(text);
+
+        if column % 7 == 6 {
+            ui.end_row();
+        }
+    }
+}
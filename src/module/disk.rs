@@ -0,0 +1,322 @@
+use std::time::Instant;
+
+use serde::Deserialize;
+use systemstat::{Filesystem, Platform};
+
+use super::{
+    format::{Template, Units, Value},
+    Content, Module,
+};
+use crate::bar::{Bar, Color};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DiskConfig {
+    /// Mount points, `*` and `?` match within a path component
+    #[serde(default = "default_mounts")]
+    mounts: Vec<String>,
+    /// Template with {mount} {device} {used} {free} {total} {percent} {percent-free}
+    #[serde(default = "default_format")]
+    format: String,
+    /// Shown instead of `format` after `cycle-format`
+    #[serde(default = "default_format_alt")]
+    format_alt: String,
+    #[serde(default)]
+    units: Units,
+    /// Between the mounts
+    #[serde(default = "default_separator")]
+    separator: String,
+    /// Color mounts once their used percentage reaches a threshold
+    #[serde(default)]
+    thresholds: Vec<Threshold>,
+}
+
+fn default_mounts() -> Vec<String> {
+    vec!["/".into()]
+}
+
+fn default_format() -> String {
+    "{mount} {percent}%".into()
+}
+
+fn default_format_alt() -> String {
+    "{mount} {used}/{total}".into()
+}
+
+fn default_separator() -> String {
+    "  ".into()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    /// Used percentage the color starts at
+    used: f64,
+    color: Color,
+}
+
+/// Filesystems that don't hold files, skipped when matching globs
+const PSEUDO_FILESYSTEMS: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "ramfs",
+    "rpc_pipefs",
+    "securityfs",
+    "squashfs",
+    "sysfs",
+    "tmpfs",
+    "tracefs",
+];
+
+fn is_pseudo(mount: &Filesystem) -> bool {
+    mount.total.as_u64() == 0
+        || PSEUDO_FILESYSTEMS.contains(&mount.fs_type.as_str())
+        || mount.fs_type.starts_with("fuse.gvfs")
+        || mount.fs_type == "fuse.portal"
+}
+
+/// Shell style matching, wildcards don't cross `/`
+fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match (pattern.first(), path.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], path)
+                || (path.first().is_some_and(|&c| c != b'/') && glob_match(pattern, &path[1..]))
+        }
+        (Some(b'?'), Some(&c)) if c != b'/' => glob_match(&pattern[1..], &path[1..]),
+        (Some(p), Some(c)) if p == c => glob_match(&pattern[1..], &path[1..]),
+        _ => false,
+    }
+}
+
+/// Placeholders of disk formats
+const PLACEHOLDERS: &[&str] = &[
+    "mount",
+    "device",
+    "used",
+    "free",
+    "total",
+    "percent",
+    "percent-free",
+];
+
+/// Space of a filesystem as the user sees it, `free` is what's available
+/// to unprivileged users and `used` everything else
+struct Usage {
+    used: u64,
+    free: u64,
+    total: u64,
+    percent: f64,
+}
+
+impl Usage {
+    fn of(mount: &Filesystem) -> Self {
+        let total = mount.total.as_u64();
+        // reported sizes aren't always consistent, e.g. on overlays
+        let free = mount.avail.as_u64().min(total);
+        let used = total - free;
+        Self {
+            used,
+            free,
+            total,
+            percent: used as f64 / total.max(1) as f64 * 100.,
+        }
+    }
+}
+
+pub struct Disk {
+    sys: systemstat::System,
+    mounts: Vec<String>,
+    /// Every mounted filesystem, scanned at most once per poll interval
+    filesystems: Vec<Filesystem>,
+    scanned: Option<Instant>,
+    format: Template,
+    format_alt: Template,
+    units: Units,
+    separator: String,
+    /// Sorted by percentage
+    thresholds: Vec<Threshold>,
+    /// Show `format-alt`
+    alt: bool,
+    /// Highest used percentage of the shown mounts
+    level: Option<f32>,
+}
+
+impl Disk {
+    pub fn new(config: DiskConfig) -> Result<Self, String> {
+        let mut thresholds = config.thresholds;
+        thresholds.sort_by(|a, b| a.used.total_cmp(&b.used));
+
+        Ok(Self {
+            sys: systemstat::System::new(),
+            mounts: config.mounts,
+            format: Template::parse(&config.format, PLACEHOLDERS)?,
+            format_alt: Template::parse(&config.format_alt, PLACEHOLDERS)?,
+            units: config.units,
+            separator: config.separator,
+            thresholds,
+            alt: false,
+            level: None,
+            filesystems: Vec::new(),
+            scanned: None,
+        })
+    }
+
+    fn scan(&mut self) {
+        if self
+            .scanned
+            .is_some_and(|scanned| scanned.elapsed() < super::POLL_INTERVAL)
+        {
+            return;
+        }
+        self.scanned = Some(Instant::now());
+        self.filesystems = self.sys.mounts().unwrap_or_default();
+    }
+
+    /// Configured mounts in order, globs expanded to the real
+    /// filesystems they match
+    fn selected(&self, mounts: &[Filesystem]) -> Vec<usize> {
+        let mut selected = Vec::new();
+        for pattern in &self.mounts {
+            let mut matches = mounts
+                .iter()
+                .enumerate()
+                .filter(|(_, mount)| {
+                    if pattern.contains(['*', '?']) {
+                        !is_pseudo(mount)
+                            && glob_match(pattern.as_bytes(), mount.fs_mounted_on.as_bytes())
+                    } else {
+                        &mount.fs_mounted_on == pattern
+                    }
+                })
+                .map(|(i, _)| i)
+                .filter(|i| !selected.contains(i))
+                .collect::<Vec<_>>();
+            // stacked mounts on the same path, only the last one is visible
+            matches.reverse();
+            matches.sort_by_key(|&i| &mounts[i].fs_mounted_on);
+            matches.dedup_by_key(|&mut i| &mounts[i].fs_mounted_on);
+            selected.extend(matches);
+        }
+        selected
+    }
+
+    fn color(&self, percent: f64, bar: &Bar) -> egui::Color32 {
+        self.thresholds
+            .iter()
+            .rev()
+            .find(|threshold| percent >= threshold.used)
+            .map_or(bar.text_secondary.into(), |threshold| {
+                threshold.color.into()
+            })
+    }
+
+    fn value(&self, name: &str, mount: &Filesystem) -> Option<Value> {
+        let usage = Usage::of(mount);
+
+        Some(match name {
+            "mount" => Value::Text(mount.fs_mounted_on.clone()),
+            "device" => Value::Text(mount.fs_mounted_from.clone()),
+            "used" => Value::bytes(usage.used, self.units),
+            "free" => Value::bytes(usage.free, self.units),
+            "total" => Value::bytes(usage.total, self.units),
+            "percent" => Value::number(usage.percent, 0),
+            "percent-free" => Value::number(100. - usage.percent, 0),
+            _ => return None,
+        })
+    }
+
+    /// Values are colored by the mount's usage
+    fn render(&self, content: Content, mount: &Filesystem, bar: &Bar) -> Content {
+        let value_color = self.color(Usage::of(mount).percent, bar);
+        let format = if self.alt {
+            &self.format_alt
+        } else {
+            &self.format
+        };
+
+        format.render(content, bar, value_color, |name| self.value(name, mount))
+    }
+}
+
+impl Module for Disk {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        ctx.request_repaint_after(super::POLL_INTERVAL);
+        self.scan();
+        let mounts = &self.filesystems;
+        let mut content = Content::default();
+        let selected = self.selected(mounts);
+        let level = selected
+            .iter()
+            .map(|&index| Usage::of(&mounts[index]).percent as f32)
+            .reduce(f32::max);
+
+        for (i, &index) in selected.iter().enumerate() {
+            if i > 0 {
+                content = content.push(self.separator.clone(), bar.text);
+            }
+            content = self.render(content, &mounts[index], bar);
+        }
+        self.level = level;
+        content
+    }
+
+    fn tooltip(&mut self, bar: &Bar) -> Option<Content> {
+        self.scan();
+        if self.filesystems.is_empty() {
+            return None;
+        }
+        let mut content = Content::default();
+
+        let mounts = self.filesystems.iter().filter(|mount| !is_pseudo(mount));
+        for (i, mount) in mounts.enumerate() {
+            let Usage {
+                used,
+                total,
+                percent,
+                ..
+            } = Usage::of(mount);
+            let separator = if i == 0 { "" } else { "\n" };
+
+            content = content
+                .push(format!("{separator}{} ", mount.fs_mounted_on), bar.text)
+                .push(
+                    format!(
+                        "{} / {} ({percent:.0}%)",
+                        self.units.format(used),
+                        self.units.format(total)
+                    ),
+                    self.color(percent, bar),
+                );
+        }
+        Some(content)
+    }
+
+    /// None with `thresholds`, `states` would recolor every mount by
+    /// the fullest one
+    fn level(&self) -> Option<f32> {
+        self.level.filter(|_| self.thresholds.is_empty())
+    }
+
+    fn on_action(&mut self, action: &str) -> bool {
+        match action {
+            "cycle-format" => self.alt = !self.alt,
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use super::{markup, Content, Module};
+use crate::bar::Bar;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ExecConfig {
+    /// Run with `sh -c`
+    command: String,
+    /// Seconds between runs, 0 keeps the command running and shows
+    /// every line it prints
+    #[serde(default = "default_interval")]
+    interval: f64,
+    /// Parse the output as markup
+    #[serde(default = "default_markup")]
+    markup: bool,
+}
+
+fn default_interval() -> f64 {
+    5.
+}
+
+fn default_markup() -> bool {
+    true
+}
+
+/// Wait before restarting a persistent command that exited
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+type Output = Arc<Mutex<Option<String>>>;
+
+pub struct Exec {
+    command: String,
+    interval: Duration,
+    markup: bool,
+    /// Last line of output, written by the command's thread
+    output: Output,
+    /// The thread is started on the first update, modules are also
+    /// created to validate the config
+    started: bool,
+    /// Output the last markup error was reported for
+    failed: Option<String>,
+}
+
+impl Exec {
+    pub fn new(config: ExecConfig) -> Result<Self, String> {
+        if config.command.trim().is_empty() {
+            return Err("command is empty".into());
+        }
+        let interval = Duration::try_from_secs_f64(config.interval)
+            .map_err(|_| format!("invalid interval {}", config.interval))?;
+
+        Ok(Self {
+            command: config.command,
+            interval,
+            markup: config.markup,
+            output: Output::default(),
+            started: false,
+            failed: None,
+        })
+    }
+
+    fn start(&self, ctx: &egui::Context) {
+        let command = self.command.clone();
+        let interval = self.interval;
+        let output = Arc::downgrade(&self.output);
+        let ctx = ctx.clone();
+
+        std::thread::spawn(move || {
+            if interval.is_zero() {
+                persistent(&command, &output, &ctx);
+            } else {
+                periodic(&command, interval, &output, &ctx);
+            }
+        });
+    }
+}
+
+fn spawn(command: &str, stdout: Stdio) -> Option<Child> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .spawn()
+        .map_err(|err| log::warn!("failed to run \"{command}\": {err}"))
+        .ok()
+}
+
+/// Store a line of output, false once the module is gone
+fn publish(output: &Weak<Mutex<Option<String>>>, line: String, ctx: &egui::Context) -> bool {
+    let Some(output) = output.upgrade() else {
+        return false;
+    };
+    let mut output = output.lock().unwrap();
+    if output.as_ref() != Some(&line) {
+        *output = Some(line);
+        ctx.request_repaint();
+    }
+    true
+}
+
+/// Run the command every `interval`, showing the last line it printed
+fn periodic(
+    command: &str,
+    interval: Duration,
+    output: &Weak<Mutex<Option<String>>>,
+    ctx: &egui::Context,
+) {
+    loop {
+        let line = spawn(command, Stdio::piped())
+            .and_then(|child| child.wait_with_output().ok())
+            .map(|out| {
+                let stdout = String::from_utf8_lossy(&out.stdout);
+                stdout.lines().last().unwrap_or_default().to_string()
+            })
+            .unwrap_or_default();
+
+        if !publish(output, line, ctx) {
+            return;
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Keep the command running, every line it prints replaces the last
+fn persistent(command: &str, output: &Weak<Mutex<Option<String>>>, ctx: &egui::Context) {
+    loop {
+        let Some(mut child) = spawn(command, Stdio::piped()) else {
+            return;
+        };
+        let stdout = child.stdout.take().unwrap();
+
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if !publish(output, line, ctx) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+        }
+
+        let _ = child.wait();
+        if output.strong_count() == 0 {
+            return;
+        }
+        log::warn!("\"{command}\" exited, restarting");
+        std::thread::sleep(RESTART_DELAY);
+    }
+}
+
+impl Module for Exec {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        if !self.started {
+            self.started = true;
+            self.start(ctx);
+        }
+
+        let Some(output) = self.output.lock().unwrap().clone() else {
+            return Content::default();
+        };
+        if !self.markup {
+            return Content::default().push(output, bar.text);
+        }
+
+        match markup::parse(&output, bar.text.into(), bar) {
+            Ok(content) => content,
+            Err(err) => {
+                if self.failed.as_ref() != Some(&output) {
+                    log::warn!("invalid markup from \"{}\": {err}", self.command);
+                    self.failed = Some(output.clone());
+                }
+                Content::default().push(output, bar.text)
+            }
+        }
+    }
+}
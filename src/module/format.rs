@@ -0,0 +1,683 @@
+//! Templates for the text of modules, e.g. `{mount} {used|gib:.1}/{total}[ ({label})]`
+//!
+//! - `{name}` is replaced by a value of the module, `{name:>5.1}` aligns it
+//!   (`<`, `>` or `^`) to a width and sets the decimal places of numbers
+//! - `{name|filter}` converts it first: `iec` and `si` scale bytes to a unit,
+//!   `kib` .. `tib` and `kb` .. `tb` turn them into a number of that unit,
+//!   `upper` and `lower` change the case of text
+//! - `[...]` is only shown when every placeholder inside has a value
+//! - `{#e06c75}...{/}` colors a span, bar colors work by name:
+//!   `{#text}`, `{#secondary}`, `{#warning}`, `{#critical}`
+//! - `{{`, `}}`, `[[` and `]]` are literal braces and brackets
+//!
+//! Text values keep the text color, numbers are drawn as values
+//! together with text stuck to them (the `%` of `{percent}%`)
+
+use egui::Color32;
+use serde::Deserialize;
+
+use super::Content;
+use crate::bar::{Bar, Color};
+
+/// A reading handed to a template
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    /// Shown with `precision` decimal places unless the template says otherwise
+    Number {
+        value: f64,
+        precision: usize,
+    },
+    Bytes {
+        bytes: u64,
+        units: Units,
+    },
+}
+
+impl Value {
+    pub fn number(value: impl Into<f64>, precision: usize) -> Self {
+        Value::Number {
+            value: value.into(),
+            precision,
+        }
+    }
+
+    pub fn bytes(bytes: u64, units: Units) -> Self {
+        Value::Bytes { bytes, units }
+    }
+}
+
+/// Powers of 1024 (GiB) or of 1000 (GB)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Iec,
+    Si,
+}
+
+impl Units {
+    pub fn format(self, bytes: u64) -> String {
+        let (base, prefixes) = match self {
+            Units::Iec => (1024., ["B", "KiB", "MiB", "GiB", "TiB", "PiB"]),
+            Units::Si => (1000., ["B", "kB", "MB", "GB", "TB", "PB"]),
+        };
+
+        let mut value = bytes as f64;
+        let mut prefix = 0;
+        while value >= base && prefix < prefixes.len() - 1 {
+            value /= base;
+            prefix += 1;
+        }
+
+        if prefix == 0 {
+            format!("{bytes} B")
+        } else {
+            format!("{value:.1} {}", prefixes[prefix])
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Filter {
+    Human(Units),
+    /// Bytes divided into a number of some unit
+    Scale(f64),
+    Upper,
+    Lower,
+}
+
+impl Filter {
+    fn parse(name: &str) -> Option<Self> {
+        let kilo = |power: i32| Filter::Scale(1000f64.powi(power));
+        let kibi = |power: i32| Filter::Scale(1024f64.powi(power));
+        Some(match name {
+            "iec" => Filter::Human(Units::Iec),
+            "si" => Filter::Human(Units::Si),
+            "kib" => kibi(1),
+            "mib" => kibi(2),
+            "gib" => kibi(3),
+            "tib" => kibi(4),
+            "kb" => kilo(1),
+            "mb" => kilo(2),
+            "gb" => kilo(3),
+            "tb" => kilo(4),
+            "upper" => Filter::Upper,
+            "lower" => Filter::Lower,
+            _ => return None,
+        })
+    }
+
+    fn apply(self, value: Value) -> Value {
+        match (self, value) {
+            (Filter::Human(units), Value::Bytes { bytes, .. }) => Value::bytes(bytes, units),
+            (Filter::Scale(divisor), Value::Bytes { bytes, .. }) => {
+                Value::number(bytes as f64 / divisor, 1)
+            }
+            (Filter::Upper, Value::Text(text)) => Value::Text(text.to_uppercase()),
+            (Filter::Lower, Value::Text(text)) => Value::Text(text.to_lowercase()),
+            (_, value) => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alignment {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Placeholder {
+    name: String,
+    filters: Vec<Filter>,
+    alignment: Alignment,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Placeholder {
+    /// The value as text, and whether it is drawn as a value
+    fn render(&self, value: Value) -> (String, bool) {
+        let value = self
+            .filters
+            .iter()
+            .fold(value, |value, filter| filter.apply(value));
+        let (text, is_value) = match value {
+            Value::Text(text) => (text, false),
+            Value::Number { value, precision } => (
+                format!("{value:.*}", self.precision.unwrap_or(precision)),
+                true,
+            ),
+            Value::Bytes { bytes, units } => (units.format(bytes), true),
+        };
+
+        let padding = self.width.saturating_sub(text.chars().count());
+        let (left, right) = match self.alignment {
+            Alignment::Left => (0, padding),
+            Alignment::Right => (padding, 0),
+            Alignment::Center => (padding / 2, padding - padding / 2),
+        };
+        let text = format!("{}{text}{}", " ".repeat(left), " ".repeat(right));
+        (text, is_value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum NamedColor {
+    Text,
+    Secondary,
+    Warning,
+    Critical,
+    Rgb(Color32),
+}
+
+impl NamedColor {
+    fn resolve(&self, bar: &Bar) -> Color32 {
+        match self {
+            NamedColor::Text => bar.text.into(),
+            NamedColor::Secondary => bar.text_secondary.into(),
+            NamedColor::Warning => bar.warning.into(),
+            NamedColor::Critical => bar.critical.into(),
+            NamedColor::Rgb(color) => *color,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// `attached` text directly follows a placeholder and is drawn like it
+    Text {
+        text: String,
+        attached: bool,
+    },
+    Placeholder(Placeholder),
+    Section(Vec<Node>),
+    Colored(NamedColor, Vec<Node>),
+}
+
+/// A parsed format string
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+/// Where a run of nodes ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum End {
+    Template,
+    Section,
+    Color,
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    /// Byte offset into `source`
+    position: usize,
+    placeholders: &'a [&'a str],
+}
+
+impl Template {
+    /// Parse `source`, only the given placeholder names are accepted
+    pub fn parse(source: &str, placeholders: &[&str]) -> Result<Self, String> {
+        let mut parser = Parser {
+            source,
+            position: 0,
+            placeholders,
+        };
+        let nodes = parser
+            .nodes(End::Template)
+            .map_err(|(column, err)| format!("format \"{source}\", column {column}: {err}"))?;
+        Ok(Self { nodes })
+    }
+
+    /// Append the template to `content`, values are drawn in `value_color`
+    pub fn render(
+        &self,
+        content: Content,
+        bar: &Bar,
+        value_color: Color32,
+        lookup: impl Fn(&str) -> Option<Value>,
+    ) -> Content {
+        let mut renderer = Renderer {
+            bar,
+            value_color,
+            lookup: &lookup,
+        };
+        renderer.nodes(content, &self.nodes, None).0
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    fn column(&self) -> usize {
+        self.source[..self.position].chars().count() + 1
+    }
+
+    fn nodes(&mut self, end: End) -> Result<Vec<Node>, (usize, String)> {
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+        // literal text right after a placeholder sticks to it until whitespace
+        let mut after_placeholder = false;
+
+        let flush = |nodes: &mut Vec<Node>, text: &mut String, after_placeholder: &mut bool| {
+            if text.is_empty() {
+                return;
+            }
+            if *after_placeholder {
+                let split = text.find(char::is_whitespace).unwrap_or(text.len());
+                let (attached, rest) = text.split_at(split);
+                if !attached.is_empty() {
+                    nodes.push(Node::Text {
+                        text: attached.into(),
+                        attached: true,
+                    });
+                }
+                if !rest.is_empty() {
+                    nodes.push(Node::Text {
+                        text: rest.into(),
+                        attached: false,
+                    });
+                }
+            } else {
+                nodes.push(Node::Text {
+                    text: text.clone(),
+                    attached: false,
+                });
+            }
+            text.clear();
+            *after_placeholder = false;
+        };
+
+        loop {
+            let rest = self.rest();
+            let Some(c) = rest.chars().next() else {
+                return match end {
+                    End::Template | End::Color => {
+                        flush(&mut nodes, &mut text, &mut after_placeholder);
+                        Ok(nodes)
+                    }
+                    End::Section => Err((self.column(), "unclosed section, missing \"]\"".into())),
+                };
+            };
+
+            if ["{{", "}}", "[[", "]]"]
+                .iter()
+                .any(|escape| rest.starts_with(escape))
+            {
+                text.push(c);
+                self.position += 2;
+                continue;
+            }
+
+            match c {
+                '{' if rest.starts_with("{/}") => {
+                    if end != End::Color {
+                        return Err((self.column(), "\"{/}\" without a color to end".into()));
+                    }
+                    self.position += 3;
+                    flush(&mut nodes, &mut text, &mut after_placeholder);
+                    return Ok(nodes);
+                }
+                '{' => {
+                    flush(&mut nodes, &mut text, &mut after_placeholder);
+                    let start = self.column();
+                    let close = rest
+                        .find('}')
+                        .ok_or((start, "unclosed placeholder, missing \"}\"".to_string()))?;
+                    let inner = &rest[1..close];
+                    self.position += close + 1;
+
+                    if let Some(color) = inner.strip_prefix('#') {
+                        let color = parse_color(color).map_err(|err| (start, err))?;
+                        let inner = self.nodes(End::Color)?;
+                        nodes.push(Node::Colored(color, inner));
+                    } else {
+                        let placeholder = self.placeholder(inner).map_err(|err| (start, err))?;
+                        nodes.push(Node::Placeholder(placeholder));
+                        after_placeholder = true;
+                    }
+                }
+                '[' => {
+                    flush(&mut nodes, &mut text, &mut after_placeholder);
+                    self.position += 1;
+                    nodes.push(Node::Section(self.nodes(End::Section)?));
+                }
+                ']' if end == End::Section => {
+                    self.position += 1;
+                    flush(&mut nodes, &mut text, &mut after_placeholder);
+                    return Ok(nodes);
+                }
+                '}' | ']' => {
+                    return Err((
+                        self.column(),
+                        format!("unexpected \"{c}\", write \"{c}{c}\" for a literal one"),
+                    ))
+                }
+                _ => {
+                    text.push(c);
+                    self.position += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    /// `name|filter|filter:spec`
+    fn placeholder(&self, inner: &str) -> Result<Placeholder, String> {
+        let (head, spec) = inner.split_once(':').unwrap_or((inner, ""));
+        let mut parts = head.split('|');
+        let name = parts.next().unwrap_or_default().trim();
+
+        if !self.placeholders.contains(&name) {
+            return Err(format!(
+                "unknown placeholder \"{name}\", expected one of {}",
+                self.placeholders.join(", ")
+            ));
+        }
+
+        let filters = parts
+            .map(|filter| {
+                Filter::parse(filter.trim()).ok_or_else(|| {
+                    format!(
+                        "unknown filter \"{}\", expected one of iec, si, kib, mib, gib, tib, kb, mb, gb, tb, upper, lower",
+                        filter.trim()
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (alignment, spec) = match spec.chars().next() {
+            Some('<') => (Alignment::Left, &spec[1..]),
+            Some('>') => (Alignment::Right, &spec[1..]),
+            Some('^') => (Alignment::Center, &spec[1..]),
+            // numbers line up on the right, like in format!
+            _ => (Alignment::Right, spec),
+        };
+        let (width, precision) = spec.split_once('.').unwrap_or((spec, ""));
+        let invalid = || format!("invalid spec \"{spec}\", expected e.g. \">5.1\"");
+
+        Ok(Placeholder {
+            name: name.into(),
+            filters,
+            alignment,
+            width: if width.is_empty() {
+                0
+            } else {
+                width.parse().map_err(|_| invalid())?
+            },
+            precision: if spec.contains('.') {
+                Some(precision.parse().map_err(|_| invalid())?)
+            } else {
+                None
+            },
+        })
+    }
+}
+
+fn parse_color(name: &str) -> Result<NamedColor, String> {
+    Ok(match name {
+        "text" => NamedColor::Text,
+        "secondary" => NamedColor::Secondary,
+        "warning" => NamedColor::Warning,
+        "critical" => NamedColor::Critical,
+        hex => {
+            let color = format!("#{hex}").parse::<Color>().map_err(|_| {
+                format!("invalid color \"#{hex}\", expected a hex color or text, secondary, warning, critical")
+            })?;
+            NamedColor::Rgb(color.into())
+        }
+    })
+}
+
+struct Renderer<'a> {
+    bar: &'a Bar,
+    value_color: Color32,
+    lookup: &'a dyn Fn(&str) -> Option<Value>,
+}
+
+impl Renderer<'_> {
+    /// Returns the content and whether every placeholder had a value
+    fn nodes(
+        &mut self,
+        mut content: Content,
+        nodes: &[Node],
+        color: Option<Color32>,
+    ) -> (Content, bool) {
+        let mut complete = true;
+        // whether the last placeholder was drawn as a value
+        let mut last_value = false;
+
+        for node in nodes {
+            match node {
+                Node::Text { text, attached } => {
+                    content = self.push(content, text, *attached && last_value, color);
+                }
+                Node::Placeholder(placeholder) => match (self.lookup)(&placeholder.name) {
+                    Some(value) => {
+                        let (text, is_value) = placeholder.render(value);
+                        last_value = is_value;
+                        content = self.push(content, &text, is_value, color);
+                    }
+                    None => {
+                        complete = false;
+                        last_value = false;
+                    }
+                },
+                Node::Section(nodes) => {
+                    let (section, section_complete) = self.nodes(Content::default(), nodes, color);
+                    if section_complete {
+                        content.extend(section);
+                    }
+                }
+                Node::Colored(named, nodes) => {
+                    let color = Some(named.resolve(self.bar));
+                    let (inner, inner_complete) = self.nodes(content, nodes, color);
+                    content = inner;
+                    complete &= inner_complete;
+                }
+            }
+        }
+        (content, complete)
+    }
+
+    fn push(&self, content: Content, text: &str, value: bool, color: Option<Color32>) -> Content {
+        match (color, value) {
+            // explicitly colored spans keep their color
+            (Some(color), _) => content.push(text, color),
+            (None, true) => content.value(text, self.value_color),
+            (None, false) => content.push(text, self.bar.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLACEHOLDERS: &[&str] = &["used", "total", "label"];
+
+    fn parse(source: &str) -> Result<Vec<Node>, String> {
+        Template::parse(source, PLACEHOLDERS).map(|template| template.nodes)
+    }
+
+    fn text(text: &str, attached: bool) -> Node {
+        Node::Text {
+            text: text.into(),
+            attached,
+        }
+    }
+
+    fn placeholder(name: &str) -> Placeholder {
+        Placeholder {
+            name: name.into(),
+            filters: Vec::new(),
+            alignment: Alignment::Right,
+            width: 0,
+            precision: None,
+        }
+    }
+
+    #[test]
+    fn text_sticks_to_placeholders_until_whitespace() {
+        assert_eq!(
+            parse("ram {used}% of {total}").unwrap(),
+            [
+                text("ram ", false),
+                Node::Placeholder(placeholder("used")),
+                text("%", true),
+                text(" of ", false),
+                Node::Placeholder(placeholder("total")),
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_braces_and_brackets() {
+        assert_eq!(
+            parse("{{{used}}} [[x]]").unwrap(),
+            [
+                text("{", false),
+                Node::Placeholder(placeholder("used")),
+                text("}", true),
+                text(" [x]", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn specs_and_filters() {
+        let [Node::Placeholder(used)] = &parse("{used|gib:<6.2}").unwrap()[..] else {
+            panic!("expected a single placeholder");
+        };
+        assert_eq!(
+            *used,
+            Placeholder {
+                name: "used".into(),
+                filters: vec![Filter::Scale(1024f64.powi(3))],
+                alignment: Alignment::Left,
+                width: 6,
+                precision: Some(2),
+            }
+        );
+        assert_eq!(
+            used.render(Value::bytes(3 * 1024u64.pow(3) / 2, Units::Iec)),
+            ("1.50  ".into(), true)
+        );
+
+        let [Node::Placeholder(label)] = &parse("{ label | upper :^7}").unwrap()[..] else {
+            panic!("expected a single placeholder");
+        };
+        assert_eq!(
+            label.render(Value::Text("cpu".into())),
+            ("  CPU  ".into(), false)
+        );
+        let [Node::Placeholder(total)] = &parse("{total:.0}").unwrap()[..] else {
+            panic!("expected a single placeholder");
+        };
+        assert_eq!(total.render(Value::number(2.5, 2)), ("2".into(), true));
+    }
+
+    #[test]
+    fn sections_and_colors() {
+        assert_eq!(
+            parse("[{label} ]{#critical}{used}{/}").unwrap(),
+            [
+                Node::Section(vec![
+                    Node::Placeholder(placeholder("label")),
+                    text(" ", false),
+                ]),
+                Node::Colored(
+                    NamedColor::Critical,
+                    vec![Node::Placeholder(placeholder("used"))]
+                ),
+            ]
+        );
+        assert_eq!(
+            parse("{#ff000080}x").unwrap(),
+            [Node::Colored(
+                NamedColor::Rgb(Color32::from_rgba_unmultiplied(255, 0, 0, 128)),
+                vec![text("x", false)]
+            )]
+        );
+    }
+
+    #[test]
+    fn unclosed_placeholder() {
+        assert_eq!(
+            parse("ram {used").unwrap_err(),
+            "format \"ram {used\", column 5: unclosed placeholder, missing \"}\""
+        );
+    }
+
+    #[test]
+    fn unclosed_section() {
+        assert_eq!(
+            parse("[{used}").unwrap_err(),
+            "format \"[{used}\", column 8: unclosed section, missing \"]\""
+        );
+    }
+
+    #[test]
+    fn unexpected_closing() {
+        assert_eq!(
+            parse("{used}}").unwrap_err(),
+            "format \"{used}}\", column 7: unexpected \"}\", write \"}}\" for a literal one"
+        );
+        assert_eq!(
+            parse("a]").unwrap_err(),
+            "format \"a]\", column 2: unexpected \"]\", write \"]]\" for a literal one"
+        );
+        assert_eq!(
+            parse("x{/}").unwrap_err(),
+            "format \"x{/}\", column 2: \"{/}\" without a color to end"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholder() {
+        assert_eq!(
+            parse("{free}").unwrap_err(),
+            "format \"{free}\", column 1: unknown placeholder \"free\", expected one of used, total, label"
+        );
+    }
+
+    #[test]
+    fn unknown_filter() {
+        assert_eq!(
+            parse("{used|mega}").unwrap_err(),
+            "format \"{used|mega}\", column 1: unknown filter \"mega\", expected one of iec, si, kib, mib, gib, tib, kb, mb, gb, tb, upper, lower"
+        );
+    }
+
+    #[test]
+    fn invalid_spec() {
+        assert_eq!(
+            parse("{used:>x}").unwrap_err(),
+            "format \"{used:>x}\", column 1: invalid spec \"x\", expected e.g. \">5.1\""
+        );
+        assert_eq!(
+            parse("{used:5.}").unwrap_err(),
+            "format \"{used:5.}\", column 1: invalid spec \"5.\", expected e.g. \">5.1\""
+        );
+    }
+
+    #[test]
+    fn invalid_color() {
+        assert_eq!(
+            parse("{#blue}x{/}").unwrap_err(),
+            "format \"{#blue}x{/}\", column 1: invalid color \"#blue\", expected a hex color or text, secondary, warning, critical"
+        );
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        assert_eq!(
+            parse("°C {used").unwrap_err(),
+            "format \"°C {used\", column 4: unclosed placeholder, missing \"}\""
+        );
+    }
+}
@@ -0,0 +1,506 @@
+//! Status commands speaking the i3bar protocol, e.g. i3status or i3blocks
+//!
+//! The command prints a header like `{"version":1,"click_events":true}`
+//! followed by an endless JSON array, one array of blocks per line. Clicks on
+//! a block are written to the command's stdin as an endless array of events
+//! if the header asked for them. Commands without a header are shown as
+//! plain text, one line at a time.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{mpsc, Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+
+use super::{markup, Click, Content, MinWidth, Module};
+use crate::bar::{Bar, Color};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct I3barConfig {
+    /// Run with `sh -c`
+    command: String,
+    /// Drawn between blocks that ask for a separator
+    #[serde(default = "default_separator")]
+    separator: String,
+}
+
+fn default_separator() -> String {
+    "|".into()
+}
+
+/// Wait before restarting a command that exited
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    version: u32,
+    #[serde(default)]
+    click_events: bool,
+}
+
+/// A block of the status line, unknown fields like `_custom` are ignored
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Block {
+    full_text: String,
+    color: Option<String>,
+    background: Option<String>,
+    min_width: Option<BlockWidth>,
+    #[serde(default)]
+    align: Align,
+    name: Option<String>,
+    instance: Option<String>,
+    #[serde(default)]
+    urgent: bool,
+    #[serde(default = "default_true")]
+    separator: bool,
+    #[serde(default = "default_separator_width")]
+    separator_block_width: f32,
+    markup: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_separator_width() -> f32 {
+    9.
+}
+
+impl Block {
+    fn plain(text: String) -> Self {
+        Self {
+            full_text: text,
+            color: None,
+            background: None,
+            min_width: None,
+            align: Align::Left,
+            name: None,
+            instance: None,
+            urgent: false,
+            separator: false,
+            separator_block_width: 0.,
+            markup: None,
+        }
+    }
+}
+
+/// Pixels, or the width of a text
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum BlockWidth {
+    Pixels(f32),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Serialize)]
+struct ClickEvent<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<&'a str>,
+    button: u8,
+    modifiers: [&'a str; 0],
+    /// Relative to the bar's window, the root window position isn't known
+    x: i32,
+    y: i32,
+    relative_x: i32,
+    relative_y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl<'a> ClickEvent<'a> {
+    fn new(block: &'a Block, click: &Click) -> Self {
+        Self {
+            name: block.name.as_deref(),
+            instance: block.instance.as_deref(),
+            button: click.button,
+            modifiers: [],
+            x: click.window_pos.x.round() as i32,
+            y: click.window_pos.y.round() as i32,
+            relative_x: click.pos.x.round() as i32,
+            relative_y: click.pos.y.round() as i32,
+            width: click.size.x.round() as i32,
+            height: click.size.y.round() as i32,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Status {
+    /// Blocks of the last status line
+    blocks: Vec<Block>,
+    /// Click events for the running command, if it asked for them
+    events: Option<mpsc::Sender<String>>,
+}
+
+pub struct I3bar {
+    command: String,
+    separator: String,
+    /// Written by the command's thread
+    status: Arc<Mutex<Status>>,
+    /// The thread is started on the first update, modules are also
+    /// created to validate the config
+    started: bool,
+    /// Text the last markup error was reported for
+    failed: Option<String>,
+}
+
+impl I3bar {
+    pub fn new(config: I3barConfig) -> Result<Self, String> {
+        if config.command.trim().is_empty() {
+            return Err("command is empty".into());
+        }
+
+        Ok(Self {
+            command: config.command,
+            separator: config.separator,
+            status: Arc::default(),
+            started: false,
+            failed: None,
+        })
+    }
+
+    fn start(&self, ctx: &egui::Context) {
+        let command = self.command.clone();
+        let status = Arc::downgrade(&self.status);
+        let ctx = ctx.clone();
+        std::thread::spawn(move || run(&command, &status, &ctx));
+    }
+
+    fn render(&mut self, block: &Block, bar: &Bar) -> Content {
+        let color = match &block.color {
+            _ if block.urgent => bar.critical.into(),
+            Some(color) => parse_color(color).unwrap_or(bar.text.into()),
+            None => bar.text.into(),
+        };
+
+        let mut content = if block.markup.as_deref() == Some("pango") {
+            markup::parse(&block.full_text, color, bar).unwrap_or_else(|err| {
+                if self.failed.as_ref() != Some(&block.full_text) {
+                    log::warn!("invalid markup from \"{}\": {err}", self.command);
+                    self.failed = Some(block.full_text.clone());
+                }
+                Content::default().push(block.full_text.clone(), color)
+            })
+        } else {
+            Content::default().push(block.full_text.clone(), color)
+        };
+
+        if let Some(background) = block.background.as_deref().and_then(parse_color) {
+            for span in &mut content.spans {
+                if span.style.background == Color32::TRANSPARENT {
+                    span.style.background = background;
+                }
+            }
+        }
+
+        let Some(width) = &block.min_width else {
+            return content;
+        };
+        let width = match width {
+            BlockWidth::Pixels(pixels) => MinWidth::Points(*pixels),
+            BlockWidth::Text(text) => MinWidth::Text(text.clone()),
+        };
+        let align = match block.align {
+            Align::Left => egui::Align::Min,
+            Align::Center => egui::Align::Center,
+            Align::Right => egui::Align::Max,
+        };
+        Content::default().padded(content, width, align)
+    }
+}
+
+fn parse_color(color: &str) -> Option<Color32> {
+    color.parse::<Color>().ok().map(Color32::from)
+}
+
+/// Keep the command running, restarting it when it exits
+fn run(command: &str, status: &Weak<Mutex<Status>>, ctx: &egui::Context) {
+    loop {
+        let Some(mut child) = spawn(command) else {
+            return;
+        };
+        let stdout = child.stdout.take().unwrap();
+        let stdin = child.stdin.take().unwrap();
+
+        if read(command, stdout, stdin, status, ctx) {
+            let _ = child.wait();
+        } else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return;
+        }
+        if status.strong_count() == 0 {
+            return;
+        }
+        log::warn!("\"{command}\" exited, restarting");
+        std::thread::sleep(RESTART_DELAY);
+    }
+}
+
+fn spawn(command: &str) -> Option<Child> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| log::warn!("failed to run \"{command}\": {err}"))
+        .ok()
+}
+
+/// Read status lines until the command exits, false once the module is gone
+fn read(
+    command: &str,
+    stdout: impl std::io::Read,
+    stdin: ChildStdin,
+    status: &Weak<Mutex<Status>>,
+    ctx: &egui::Context,
+) -> bool {
+    let mut lines = BufReader::new(stdout).lines().map_while(Result::ok);
+    let Some(first) = lines.next() else {
+        return true;
+    };
+
+    let header = match serde_json::from_str::<Header>(&first) {
+        Ok(header) if header.version >= 1 => header,
+        _ => {
+            // Not the protocol, show each line as it is
+            for line in std::iter::once(first).chain(lines) {
+                if !publish(status, vec![Block::plain(line)], ctx) {
+                    return false;
+                }
+            }
+            return true;
+        }
+    };
+
+    if header.click_events {
+        let (sender, receiver) = mpsc::channel();
+        let Some(status) = status.upgrade() else {
+            return false;
+        };
+        status.lock().unwrap().events = Some(sender);
+        std::thread::spawn(move || write_events(stdin, receiver));
+    }
+
+    let mut reported = false;
+    for line in lines {
+        let Some(line) = body_line(&line) else {
+            continue;
+        };
+        match serde_json::from_str::<Vec<Block>>(line) {
+            Ok(blocks) => {
+                if !publish(status, blocks, ctx) {
+                    return false;
+                }
+            }
+            // a broken producer would log every second, once is enough
+            Err(err) if !reported => {
+                log::warn!("invalid status line from \"{command}\": {err}");
+                reported = true;
+            }
+            Err(_) => {}
+        }
+    }
+    true
+}
+
+/// The array of blocks on a line of the body, which is one endless array
+/// with an element per line, separated by a comma at either end
+fn body_line(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix(',').unwrap_or(line).trim_start();
+    let line = line.strip_suffix(',').unwrap_or(line).trim_end();
+    let line = match line.strip_prefix('[') {
+        Some(rest) if rest.trim_start().starts_with('[') => rest.trim_start(),
+        Some(rest) if rest.trim().is_empty() => return None,
+        _ => line,
+    };
+    (!line.is_empty() && line != "]").then_some(line)
+}
+
+/// Store the blocks of a status line, false once the module is gone
+fn publish(status: &Weak<Mutex<Status>>, blocks: Vec<Block>, ctx: &egui::Context) -> bool {
+    let Some(status) = status.upgrade() else {
+        return false;
+    };
+    let mut status = status.lock().unwrap();
+    if status.blocks != blocks {
+        status.blocks = blocks;
+        ctx.request_repaint();
+    }
+    true
+}
+
+/// Forward click events to the command, until it exits or is restarted
+fn write_events(mut stdin: ChildStdin, receiver: mpsc::Receiver<String>) {
+    if writeln!(stdin, "[").is_err() {
+        return;
+    }
+    let mut first = true;
+    for event in receiver {
+        let separator = if first { "" } else { "," };
+        first = false;
+        if writeln!(stdin, "{separator}{event}")
+            .and_then(|_| stdin.flush())
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+impl Module for I3bar {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        if !self.started {
+            self.started = true;
+            self.start(ctx);
+        }
+
+        let blocks = self.status.lock().unwrap().blocks.clone();
+        let mut content = Content::default();
+        for (i, block) in blocks.iter().enumerate() {
+            if i > 0 {
+                let previous = &blocks[i - 1];
+                let gap = previous.separator_block_width;
+                if previous.separator && !self.separator.is_empty() {
+                    content = content
+                        .space(gap / 2.)
+                        .push(self.separator.clone(), bar.text_secondary)
+                        .space(gap / 2.);
+                } else {
+                    content = content.space(gap);
+                }
+            }
+            let block = self.render(block, bar);
+            content = content.tagged(i, block);
+        }
+        content
+    }
+
+    fn on_click(&mut self, click: Click) -> bool {
+        let status = self.status.lock().unwrap();
+        let (Some(events), Some(block)) = (&status.events, status.blocks.get(click.tag)) else {
+            return false;
+        };
+
+        let event = serde_json::to_string(&ClickEvent::new(block, &click)).unwrap();
+        events.send(event).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers() {
+        let header = serde_json::from_str::<Header>(r#"{"version":1}"#).unwrap();
+        assert_eq!(header.version, 1);
+        assert!(!header.click_events);
+
+        let header = serde_json::from_str::<Header>(
+            r#"{"version":1,"click_events":true,"stop_signal":10,"cont_signal":12}"#,
+        )
+        .unwrap();
+        assert!(header.click_events);
+
+        assert!(serde_json::from_str::<Header>("plain text").is_err());
+    }
+
+    #[test]
+    fn block_defaults() {
+        let blocks = serde_json::from_str::<Vec<Block>>(r#"[{"full_text":"cpu 5%"}]"#).unwrap();
+        let mut expected = Block::plain("cpu 5%".into());
+        expected.separator = true;
+        expected.separator_block_width = 9.;
+        assert_eq!(blocks, [expected]);
+    }
+
+    #[test]
+    fn block_fields() {
+        let blocks = serde_json::from_str::<Vec<Block>>(
+            r##"[
+                {"full_text":"a","min_width":120,"align":"right","urgent":true,
+                 "name":"cpu","instance":"0","separator":false,
+                 "separator_block_width":4,"color":"#ff0000","markup":"pango",
+                 "short_text":"b","_custom":{"x":1}},
+                {"full_text":"b","min_width":"100%","align":"center","background":"#000000"}
+            ]"##,
+        )
+        .unwrap();
+
+        let [first, second] = &blocks[..] else {
+            panic!("expected two blocks");
+        };
+        assert_eq!(first.min_width, Some(BlockWidth::Pixels(120.)));
+        assert_eq!(first.align, Align::Right);
+        assert!(first.urgent);
+        assert_eq!(first.name.as_deref(), Some("cpu"));
+        assert_eq!(first.instance.as_deref(), Some("0"));
+        assert!(!first.separator);
+        assert_eq!(first.separator_block_width, 4.);
+        assert_eq!(first.color.as_deref(), Some("#ff0000"));
+        assert_eq!(first.markup.as_deref(), Some("pango"));
+
+        assert_eq!(second.min_width, Some(BlockWidth::Text("100%".into())));
+        assert_eq!(second.align, Align::Center);
+        assert_eq!(second.background.as_deref(), Some("#000000"));
+
+        assert!(serde_json::from_str::<Vec<Block>>(r#"[{"align":"left"}]"#).is_err());
+    }
+
+    #[test]
+    fn body_lines() {
+        let blocks = r#"[{"full_text":"a"}]"#;
+        assert_eq!(body_line("["), None);
+        assert_eq!(body_line(" [ "), None);
+        assert_eq!(body_line(&format!("[{blocks}")), Some(blocks));
+        assert_eq!(body_line(&format!("[ {blocks},")), Some(blocks));
+        assert_eq!(body_line(&format!(",{blocks}")), Some(blocks));
+        assert_eq!(body_line(&format!("  , {blocks} ")), Some(blocks));
+        assert_eq!(body_line(&format!("{blocks},")), Some(blocks));
+        assert_eq!(body_line("[]"), Some("[]"));
+        assert_eq!(body_line("]"), None);
+        assert_eq!(body_line(""), None);
+    }
+
+    #[test]
+    fn click_events() {
+        let mut block = Block::plain("a".into());
+        let click = Click {
+            tag: 0,
+            button: 3,
+            pos: egui::vec2(4.4, 10.),
+            size: egui::vec2(40.6, 20.),
+            window_pos: egui::pos2(104.5, 10.),
+        };
+        assert_eq!(
+            serde_json::to_string(&ClickEvent::new(&block, &click)).unwrap(),
+            r#"{"button":3,"modifiers":[],"x":105,"y":10,"relative_x":4,"relative_y":10,"width":41,"height":20}"#
+        );
+
+        block.name = Some("cpu".into());
+        block.instance = Some("0".into());
+        assert_eq!(
+            serde_json::to_string(&ClickEvent::new(&block, &click)).unwrap(),
+            r#"{"name":"cpu","instance":"0","button":3,"modifiers":[],"x":105,"y":10,"relative_x":4,"relative_y":10,"width":41,"height":20}"#
+        );
+    }
+}
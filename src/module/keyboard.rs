@@ -0,0 +1,388 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+};
+
+use serde::Deserialize;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xkb::{self, ConnectionExt as _},
+        xproto::{
+            ClientMessageEvent, ConnectionExt as _, CreateWindowAux, EventMask, ModMask, Window,
+            WindowClass,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+};
+
+use super::{Content, Module};
+use crate::bar::Bar;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct KeyboardConfig {
+    /// Labels by short name like `us` or full name like `English (US)`,
+    /// other layouts show their short name
+    #[serde(default)]
+    names: HashMap<String, String>,
+}
+
+/// Symbols in the keymap's name that aren't layouts
+const NOT_LAYOUTS: &[&str] = &[
+    "pc",
+    "inet",
+    "group",
+    "terminate",
+    "evdev",
+    "compose",
+    "level3",
+    "level5",
+    "lv3",
+    "lv5",
+    "srvr_ctrl",
+    "altwin",
+    "ctrl",
+    "caps",
+    "capslock",
+    "eurosign",
+    "keypad",
+    "kpdl",
+    "nbsp",
+    "shift",
+    "mod",
+];
+
+const KEYBOARD: xkb::DeviceSpec = 0x100; // XkbUseCoreKbd
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Layouts {
+    /// Short names, e.g. `us`, from the keymap's symbols
+    short: Vec<String>,
+    /// Names of the groups, e.g. `English (US)`
+    full: Vec<String>,
+    group: usize,
+}
+
+impl Layouts {
+    fn count(&self) -> usize {
+        self.full.len().max(self.short.len())
+    }
+}
+
+/// Connection of the thread following the layouts
+struct Watcher {
+    conn: Arc<RustConnection>,
+    /// Receives the event that wakes the thread up to exit
+    window: Window,
+}
+
+pub struct Keyboard {
+    names: HashMap<String, String>,
+    layouts: Arc<Mutex<Layouts>>,
+    /// Connected on the first update, modules are also created
+    /// to validate the config
+    watcher: Option<Watcher>,
+    started: bool,
+}
+
+impl Keyboard {
+    pub fn new(config: KeyboardConfig) -> Result<Self, String> {
+        Ok(Self {
+            names: config.names,
+            layouts: Arc::default(),
+            watcher: None,
+            started: false,
+        })
+    }
+
+    fn start(&mut self, ctx: &egui::Context) {
+        let (conn, window) = match connect() {
+            Ok((conn, window)) => (Arc::new(conn), window),
+            Err(err) => {
+                log::warn!("keyboard layouts unavailable: {err}");
+                return;
+            }
+        };
+        self.watcher = Some(Watcher {
+            conn: conn.clone(),
+            window,
+        });
+
+        let layouts = Arc::downgrade(&self.layouts);
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = watch(&conn, &layouts, &ctx) {
+                log::warn!("stopped following keyboard layouts: {err}");
+            }
+        });
+    }
+
+    /// Lock the group `offset` layouts away from the current one
+    fn cycle(&self, offset: isize) {
+        let Some(Watcher { conn, .. }) = &self.watcher else {
+            return;
+        };
+        let layouts = self.layouts.lock().unwrap();
+        let count = layouts.count() as isize;
+        if count < 2 {
+            return;
+        }
+        let group = (layouts.group as isize + offset).rem_euclid(count) as u8;
+
+        let locked = conn
+            .xkb_latch_lock_state(
+                KEYBOARD,
+                ModMask::default(),
+                ModMask::default(),
+                true,
+                xkb::Group::from(group),
+                ModMask::default(),
+                false,
+                0,
+            )
+            .and_then(|_| conn.flush());
+        if let Err(err) = locked {
+            log::warn!("unable to switch keyboard layout: {err}");
+        }
+    }
+
+    fn label(&self, layouts: &Layouts) -> Option<String> {
+        let short = layouts.short.get(layouts.group);
+        let full = layouts.full.get(layouts.group);
+        [short, full]
+            .into_iter()
+            .flatten()
+            .find_map(|name| self.names.get(name))
+            .or(short)
+            .or(full)
+            .cloned()
+    }
+}
+
+impl Drop for Keyboard {
+    /// The thread would only notice on the next layout change,
+    /// wake it so it closes its connection now
+    fn drop(&mut self) {
+        let Some(Watcher { conn, window }) = &self.watcher else {
+            return;
+        };
+        let event = ClientMessageEvent::new(32, *window, x11rb::NONE, [0u32; 5]);
+        let sent = conn
+            .send_event(false, *window, EventMask::NO_EVENT, event)
+            .and_then(|_| conn.flush());
+        if let Err(err) = sent {
+            log::warn!("unable to stop following keyboard layouts: {err}");
+        }
+    }
+}
+
+/// Connection with XKB enabled and a window to wake its reader with
+fn connect() -> Result<(RustConnection, Window), Box<dyn std::error::Error>> {
+    let (conn, screen) = x11rb::connect(None)?;
+    let version = conn.xkb_use_extension(1, 0)?.reply()?;
+    if !version.supported {
+        return Err("the X server doesn't support XKB".into());
+    }
+
+    let root = conn.setup().roots[screen].root;
+    let window = conn.generate_id()?;
+    conn.create_window(
+        x11rb::COPY_DEPTH_FROM_PARENT,
+        window,
+        root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        x11rb::COPY_FROM_PARENT,
+        &CreateWindowAux::new(),
+    )?;
+    Ok((conn, window))
+}
+
+/// Follow group changes and new keymaps until the module is gone
+fn watch(
+    conn: &RustConnection,
+    layouts: &Weak<Mutex<Layouts>>,
+    ctx: &egui::Context,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let details = xkb::SelectEventsAux::new()
+        .state_notify(xkb::SelectEventsAuxStateNotify {
+            affect_state: xkb::StatePart::GROUP_STATE,
+            state_details: xkb::StatePart::GROUP_STATE,
+        })
+        .names_notify(xkb::SelectEventsAuxNamesNotify {
+            affect_names: xkb::NameDetail::GROUP_NAMES | xkb::NameDetail::SYMBOLS,
+            names_details: xkb::NameDetail::GROUP_NAMES | xkb::NameDetail::SYMBOLS,
+        })
+        .new_keyboard_notify(xkb::SelectEventsAuxNewKeyboardNotify {
+            affect_new_keyboard: xkb::NKNDetail::KEYCODES,
+            new_keyboard_details: xkb::NKNDetail::KEYCODES,
+        });
+    conn.xkb_select_events(
+        KEYBOARD,
+        xkb::EventType::default(),
+        xkb::EventType::default(),
+        xkb::MapPart::default(),
+        xkb::MapPart::default(),
+        &details,
+    )?;
+    conn.flush()?;
+
+    let mut current = read_layouts(conn)?;
+    loop {
+        let Some(shared) = layouts.upgrade() else {
+            return Ok(());
+        };
+        let mut shared = shared.lock().unwrap();
+        if *shared != current {
+            *shared = current.clone();
+            ctx.request_repaint();
+        }
+        drop(shared);
+
+        match conn.wait_for_event()? {
+            Event::XkbStateNotify(event) => current.group = u8::from(event.group) as usize,
+            Event::XkbNamesNotify(_) | Event::XkbNewKeyboardNotify(_) => {
+                current = read_layouts(conn)?;
+            }
+            // sent on drop, the module is gone
+            Event::ClientMessage(_) => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+fn read_layouts(conn: &RustConnection) -> Result<Layouts, Box<dyn std::error::Error>> {
+    let names = conn
+        .xkb_get_names(
+            KEYBOARD,
+            xkb::NameDetail::GROUP_NAMES | xkb::NameDetail::SYMBOLS,
+        )?
+        .reply()?;
+    let atom_name = |atom| -> Result<String, Box<dyn std::error::Error>> {
+        let reply = conn.get_atom_name(atom)?.reply()?;
+        Ok(String::from_utf8_lossy(&reply.name).into_owned())
+    };
+
+    let full = names
+        .value_list
+        .groups
+        .unwrap_or_default()
+        .into_iter()
+        .map(atom_name)
+        .collect::<Result<Vec<_>, _>>()?;
+    let short = match names.value_list.symbols_name {
+        Some(atom) => short_names(&atom_name(atom)?),
+        None => Vec::new(),
+    };
+    let group = u8::from(conn.xkb_get_state(KEYBOARD)?.reply()?.group) as usize;
+
+    Ok(Layouts { short, full, group })
+}
+
+/// Layouts of a symbols name like `pc+us+ru:2+inet(evdev)`, by group
+fn short_names(symbols: &str) -> Vec<String> {
+    let mut layouts = Vec::new();
+    for symbol in symbols.split('+') {
+        let (symbol, group) = match symbol.split_once(':') {
+            Some((symbol, group)) => (symbol, group.parse::<usize>().ok()),
+            None => (symbol, None),
+        };
+        // drop the variant, `us(dvorak)` is still `us`
+        let name = symbol.split('(').next().unwrap_or_default();
+        if name.is_empty() || NOT_LAYOUTS.contains(&name) {
+            continue;
+        }
+
+        let index = group.map_or(layouts.len(), |group| group.saturating_sub(1));
+        if layouts.len() <= index {
+            layouts.resize(index + 1, String::new());
+        }
+        layouts[index] = name.to_string();
+    }
+    layouts
+}
+
+impl Module for Keyboard {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        if !self.started {
+            self.started = true;
+            self.start(ctx);
+        }
+
+        let layouts = self.layouts.lock().unwrap().clone();
+        match self.label(&layouts) {
+            Some(label) => Content::default().push(label, bar.text),
+            None => Content::default(),
+        }
+    }
+
+    fn tooltip(&mut self, bar: &Bar) -> Option<Content> {
+        let layouts = self.layouts.lock().unwrap();
+        let name = layouts.full.get(layouts.group)?;
+        Some(Content::default().push(name.clone(), bar.text))
+    }
+
+    fn click_action(&self) -> Option<&str> {
+        Some("next-layout")
+    }
+
+    fn on_action(&mut self, action: &str) -> bool {
+        match action {
+            "next-layout" => self.cycle(1),
+            "prev-layout" => self.cycle(-1),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::short_names;
+
+    #[test]
+    fn layouts_in_group_order() {
+        assert_eq!(short_names("pc+us+inet(evdev)"), ["us"]);
+        assert_eq!(
+            short_names("pc+us+ru:2+de:3+inet(evdev)+group(alt_shift_toggle)"),
+            ["us", "ru", "de"]
+        );
+    }
+
+    #[test]
+    fn group_indices_place_layouts() {
+        // groups don't have to be listed in order
+        assert_eq!(short_names("pc+ru:2+us:1"), ["us", "ru"]);
+        // a skipped group stays empty
+        assert_eq!(short_names("pc+us+de:3"), ["us", "", "de"]);
+        // an invalid index is treated like a missing one
+        assert_eq!(short_names("pc+us+ru:x"), ["us", "ru"]);
+    }
+
+    #[test]
+    fn variants_are_dropped() {
+        assert_eq!(
+            short_names("pc+us(dvorak)+ru(phonetic):2+inet(evdev)"),
+            ["us", "ru"]
+        );
+    }
+
+    #[test]
+    fn options_are_not_layouts() {
+        assert_eq!(
+            short_names(
+                "pc+gb+inet(evdev)+ctrl(nocaps)+compose(ralt)+level3(ralt_switch)\
+                 +terminate(ctrl_alt_bksp)+altwin(swap_lalt_lwin)+capslock(escape)"
+            ),
+            ["gb"]
+        );
+        assert!(short_names("").is_empty());
+        assert!(short_names("pc+inet(evdev)").is_empty());
+    }
+}
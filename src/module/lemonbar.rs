@@ -0,0 +1,475 @@
+//! Lines from stdin in lemonbar's format, for `pagbar --stdin`
+//!
+//! `%{l}`, `%{c}` and `%{r}` pick the panel the following text goes to,
+//! `%{F#..}`, `%{B#..}` and `%{U#..}` set the colors, `-` resets them,
+//! `%{+u}`, `%{-u}`, `%{!u}` toggle the underline, `%{R}` swaps the colors
+//! and `%{O<px>}` adds space. Text in `%{A<button>:cmd:}..%{A}` is clickable,
+//! like lemonbar the command is printed to stdout for the script to run.
+
+use std::{
+    collections::HashMap,
+    io::BufRead,
+    sync::{Arc, Mutex, OnceLock, Weak},
+};
+
+use egui::Color32;
+use serde::Deserialize;
+
+use super::{Click, Content, Module, Panel, Style};
+use crate::bar::Bar;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LemonbarConfig {
+    /// Which of the line's sections is shown
+    panel: Panel,
+}
+
+/// Last line read from stdin, shared by the slots of every bar
+#[derive(Default)]
+struct Input {
+    line: Mutex<String>,
+    /// Windows repainted when a line arrives, gone once their slot is dropped
+    contexts: Mutex<Vec<Weak<egui::Context>>>,
+}
+
+static INPUT: OnceLock<Input> = OnceLock::new();
+
+fn input() -> &'static Input {
+    INPUT.get_or_init(|| {
+        std::thread::spawn(|| {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let input = input();
+                *input.line.lock().unwrap() = line;
+                input
+                    .contexts
+                    .lock()
+                    .unwrap()
+                    .retain(|ctx| match ctx.upgrade() {
+                        Some(ctx) => {
+                            ctx.request_repaint();
+                            true
+                        }
+                        None => false,
+                    });
+            }
+            log::info!("stdin closed, keeping the last line");
+        });
+        Input::default()
+    })
+}
+
+/// Commands of a clickable area by button
+type Area = HashMap<u8, String>;
+
+/// A line split into its three sections
+struct Line {
+    sections: [Content; 3],
+    /// Tagged spans of the sections index into these
+    areas: Vec<Area>,
+}
+
+#[derive(Clone, Copy)]
+struct Format {
+    foreground: Color32,
+    background: Color32,
+    underline: bool,
+}
+
+fn parse(line: &str, bar: &Bar) -> Line {
+    let default = Format {
+        foreground: bar.text.into(),
+        background: Color32::TRANSPARENT,
+        underline: false,
+    };
+    let mut format = default;
+    let mut sections: [Content; 3] = Default::default();
+    let mut section = 0;
+    let mut areas = Vec::new();
+    // Buttons and commands of the open `%{A}` blocks, innermost last
+    let mut stack: Vec<(u8, String)> = Vec::new();
+    let mut tag = None;
+
+    let mut text = String::new();
+    let mut rest = line;
+    loop {
+        let (before, block) = match rest.find('%') {
+            Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+            None => (rest, None),
+        };
+        text.push_str(before);
+
+        let Some(block) = block else {
+            flush(&mut sections[section], &mut text, format, tag);
+            break;
+        };
+        if let Some(after) = block.strip_prefix('%') {
+            text.push('%');
+            rest = after;
+            continue;
+        }
+        let Some((commands, after)) = block.strip_prefix('{').and_then(|block| split_block(block))
+        else {
+            text.push('%');
+            rest = block;
+            continue;
+        };
+        flush(&mut sections[section], &mut text, format, tag);
+        rest = after;
+
+        let mut commands = commands;
+        while let Some(command) = next_command(&mut commands) {
+            match command.split_at(command.chars().next().map_or(0, char::len_utf8)) {
+                ("l", "") => section = 0,
+                ("c", "") => section = 1,
+                ("r", "") => section = 2,
+                ("F", color) => format.foreground = parse_color(color, default.foreground),
+                ("B", color) => format.background = parse_color(color, default.background),
+                ("R", "") => {
+                    let background = if format.background == Color32::TRANSPARENT {
+                        bar.background.into()
+                    } else {
+                        format.background
+                    };
+                    format.background = format.foreground;
+                    format.foreground = background;
+                }
+                ("+", "u") => format.underline = true,
+                ("-", "u") => format.underline = false,
+                ("!", "u") => format.underline = !format.underline,
+                ("O", offset) => match offset.parse::<f32>() {
+                    Ok(offset) => {
+                        sections[section] = std::mem::take(&mut sections[section]).space(offset)
+                    }
+                    Err(_) => log::warn!("invalid offset \"{offset}\" on stdin"),
+                },
+                ("A", "") => {
+                    stack.pop();
+                    tag = area(&stack, &mut areas);
+                }
+                ("A", action) => match parse_action(action) {
+                    Some(action) => {
+                        stack.push(action);
+                        tag = area(&stack, &mut areas);
+                    }
+                    None => log::warn!("invalid action \"%{{A{action}}}\" on stdin"),
+                },
+                // Underline colors, overlines, fonts and screens aren't supported
+                ("U" | "T" | "S", _) | ("+" | "-" | "!", "o") => {}
+                _ => log::warn!("unknown format \"%{{{command}}}\" on stdin"),
+            }
+        }
+    }
+
+    Line { sections, areas }
+}
+
+/// Append the text collected so far to `section`
+fn flush(section: &mut Content, text: &mut String, format: Format, tag: Option<usize>) {
+    if text.is_empty() {
+        return;
+    }
+    let span = Content::default().styled(
+        std::mem::take(text),
+        format.foreground,
+        Style {
+            background: format.background,
+            underline: format.underline,
+            ..Default::default()
+        },
+    );
+    let content = std::mem::take(section);
+    *section = match tag {
+        Some(tag) => content.tagged(tag, span),
+        None => {
+            let mut content = content;
+            content.extend(span);
+            content
+        }
+    };
+}
+
+/// Split `%{..}` into its commands and the text after it, `:` delimited
+/// action commands may contain `}`
+fn split_block(block: &str) -> Option<(&str, &str)> {
+    let mut in_action = false;
+    let mut chars = block.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if in_action => {
+                chars.next();
+            }
+            ':' => in_action = !in_action,
+            '}' if !in_action => return Some((&block[..i], &block[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Take the next space separated command, keeping actions whole
+fn next_command<'a>(commands: &mut &'a str) -> Option<&'a str> {
+    let trimmed = commands.trim_start();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let mut in_action = false;
+    let mut end = trimmed.len();
+    let mut chars = trimmed.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if in_action => {
+                chars.next();
+            }
+            ':' => in_action = !in_action,
+            ' ' if !in_action => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    *commands = &trimmed[end..];
+    Some(&trimmed[..end])
+}
+
+/// `#RGB`, `#RRGGBB` or lemonbar's `#AARRGGBB`, `-` for the default
+fn parse_color(color: &str, default: Color32) -> Color32 {
+    if color == "-" {
+        return default;
+    }
+    let parsed = color.strip_prefix('#').and_then(|hex| {
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let channel = |shift: u32| (value >> shift & 0xff) as u8;
+        Some(match hex.len() {
+            3 => {
+                let channel = |shift: u32| (value >> shift & 0xf) as u8 * 0x11;
+                Color32::from_rgb(channel(8), channel(4), channel(0))
+            }
+            6 => Color32::from_rgb(channel(16), channel(8), channel(0)),
+            8 => Color32::from_rgba_unmultiplied(channel(16), channel(8), channel(0), channel(24)),
+            _ => return None,
+        })
+    });
+    parsed.unwrap_or_else(|| {
+        log::warn!("invalid color \"{color}\" on stdin");
+        default
+    })
+}
+
+/// `:cmd:` or `3:cmd:`, buttons default to the left one
+fn parse_action(action: &str) -> Option<(u8, String)> {
+    let (button, command) = action.split_once(':')?;
+    let button = if button.is_empty() {
+        1
+    } else {
+        button
+            .parse()
+            .ok()
+            .filter(|button| (1..=5).contains(button))?
+    };
+    let command = command.strip_suffix(':')?.replace("\\:", ":");
+    Some((button, command))
+}
+
+/// Tag of the area covered by every open action
+fn area(stack: &[(u8, String)], areas: &mut Vec<Area>) -> Option<usize> {
+    if stack.is_empty() {
+        return None;
+    }
+    // inner actions win for the same button
+    areas.push(stack.iter().cloned().collect());
+    Some(areas.len() - 1)
+}
+
+pub struct Lemonbar {
+    section: usize,
+    /// Clickable areas of the line shown last
+    areas: Vec<Area>,
+    /// Context of the bar, registered for repaints on the first update.
+    /// The context is already shared, this `Arc` only exists so the
+    /// registered `Weak` dies with the module rather than the window
+    registered: Option<Arc<egui::Context>>,
+}
+
+impl Lemonbar {
+    pub fn new(config: LemonbarConfig) -> Result<Self, String> {
+        let section = match config.panel {
+            Panel::First => 0,
+            Panel::Center => 1,
+            Panel::Last => 2,
+        };
+        Ok(Self {
+            section,
+            areas: Vec::new(),
+            registered: None,
+        })
+    }
+}
+
+impl Module for Lemonbar {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        let input = input();
+        if self.registered.is_none() {
+            let registered = Arc::new(ctx.clone());
+            let mut contexts = input.contexts.lock().unwrap();
+            contexts.retain(|ctx| ctx.strong_count() > 0);
+            contexts.push(Arc::downgrade(&registered));
+            self.registered = Some(registered);
+        }
+
+        let line = input.line.lock().unwrap().clone();
+        let Line {
+            sections: [left, center, right],
+            areas,
+        } = parse(&line, bar);
+        self.areas = areas;
+        match self.section {
+            0 => left,
+            1 => center,
+            _ => right,
+        }
+    }
+
+    fn on_click(&mut self, click: Click) -> bool {
+        let Some(command) = self
+            .areas
+            .get(click.tag)
+            .and_then(|area| area.get(&click.button))
+        else {
+            return false;
+        };
+        println!("{command}");
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text and tag of every span in the sections
+    fn sections(line: &Line) -> [Vec<(&str, Option<usize>)>; 3] {
+        line.sections.each_ref().map(|section| {
+            section
+                .spans
+                .iter()
+                .map(|span| (span.text.as_str(), span.tag))
+                .collect()
+        })
+    }
+
+    fn area(commands: &[(u8, &str)]) -> Area {
+        commands
+            .iter()
+            .map(|&(button, command)| (button, command.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn panels() {
+        let line = parse("a%{c}b%{r}c%{l}d %{c r}e", &Bar::test());
+        assert_eq!(
+            sections(&line),
+            [
+                vec![("a", None), ("d ", None)],
+                vec![("b", None)],
+                vec![("c", None), ("e", None)],
+            ]
+        );
+    }
+
+    #[test]
+    fn percent_signs() {
+        let line = parse("100%% 50% %{x", &Bar::test());
+        assert_eq!(line.sections[0].text(), "100% 50% %{x");
+    }
+
+    #[test]
+    fn actions() {
+        let line = parse("%{A:echo a\\:b:}x%{A} %{A3:echo }:}y%{A}", &Bar::test());
+        assert_eq!(
+            sections(&line)[0],
+            [("x", Some(0)), (" ", None), ("y", Some(1))]
+        );
+        assert_eq!(
+            line.areas,
+            [area(&[(1, "echo a:b")]), area(&[(3, "echo }")])]
+        );
+    }
+
+    #[test]
+    fn nested_actions() {
+        let line = parse(
+            "%{A:outer:}%{A3:right:}%{A:inner:}x%{A}y%{A}z%{A}",
+            &Bar::test(),
+        );
+        assert_eq!(
+            sections(&line)[0],
+            [("x", Some(2)), ("y", Some(3)), ("z", Some(4))]
+        );
+        assert_eq!(line.areas[2], area(&[(1, "inner"), (3, "right")]));
+        assert_eq!(line.areas[3], area(&[(1, "outer"), (3, "right")]));
+        assert_eq!(line.areas[4], area(&[(1, "outer")]));
+    }
+
+    #[test]
+    fn unbalanced_actions() {
+        let line = parse("%{A}x%{A:cmd:}y", &Bar::test());
+        assert_eq!(sections(&line)[0], [("x", None), ("y", Some(0))]);
+        assert_eq!(line.areas, [area(&[(1, "cmd")])]);
+
+        let line = parse("%{A6:cmd:}x", &Bar::test());
+        assert_eq!(sections(&line)[0], [("x", None)]);
+        assert!(line.areas.is_empty());
+    }
+
+    #[test]
+    fn reverse() {
+        let spans = |line: &str| {
+            parse(line, &Bar::test()).sections[0]
+                .spans
+                .iter()
+                .map(|span| (span.color, span.style.background))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            spans("%{F#ff0000 R}x"),
+            [(Bar::test().background.into(), Color32::from_rgb(0xff, 0, 0))]
+        );
+        assert_eq!(
+            spans("%{F#f00}%{B#00ff00}%{R}x%{R}y"),
+            [
+                (Color32::from_rgb(0, 0xff, 0), Color32::from_rgb(0xff, 0, 0)),
+                (Color32::from_rgb(0xff, 0, 0), Color32::from_rgb(0, 0xff, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn colors() {
+        let default = Color32::from_rgb(1, 2, 3);
+        assert_eq!(
+            parse_color("#abc", default),
+            Color32::from_rgb(0xaa, 0xbb, 0xcc)
+        );
+        assert_eq!(
+            parse_color("#102030", default),
+            Color32::from_rgb(0x10, 0x20, 0x30)
+        );
+        assert_eq!(
+            parse_color("#80102030", default),
+            Color32::from_rgba_unmultiplied(0x10, 0x20, 0x30, 0x80)
+        );
+        assert_eq!(parse_color("-", default), default);
+        assert_eq!(parse_color("#12345", default), default);
+        assert_eq!(parse_color("#gggggg", default), default);
+        assert_eq!(parse_color("red", default), default);
+
+        let line = parse("%{F#12345}x", &Bar::test());
+        assert_eq!(line.sections[0].spans[0].color, Bar::test().text.into());
+    }
+}
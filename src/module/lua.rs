@@ -0,0 +1,479 @@
+//! Modules written in Lua, a script returns a table like
+//!
+//! ```lua
+//! local m = { interval = 5 }
+//! function m.update() return "<b>up</b> " .. pagbar.exec("uptime -p") end
+//! function m.on_click() pagbar.spawn("alacritty -e htop") end
+//! return m
+//! ```
+//!
+//! Scripts run on their own thread with only the `string`, `table`, `math`,
+//! `utf8` and `coroutine` libraries, the rest goes through `pagbar` helpers.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use mlua::{Function, HookTriggers, Lua, LuaOptions, RegistryKey, StdLib, Table, Value};
+use serde::Deserialize;
+
+use super::{action, markup, Content, Module};
+use crate::bar::Bar;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LuaConfig {
+    /// Relative to `$XDG_CONFIG_HOME/pagbar`
+    script: PathBuf,
+    /// Passed to the script as `pagbar.config`
+    #[serde(default)]
+    config: toml::Table,
+}
+
+/// Seconds between updates when the script doesn't set `interval`
+const DEFAULT_INTERVAL: f64 = 1.;
+/// A single call into the script is aborted after this long
+const CALL_TIMEOUT: Duration = Duration::from_secs(1);
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// What the script's thread hands to the bar
+#[derive(Default)]
+struct Output {
+    text: Option<String>,
+    tooltip: Option<String>,
+    level: Option<f32>,
+}
+
+enum Event {
+    Action(String),
+}
+
+/// When the running call has to be finished by, checked by the hook
+struct Deadline(Option<Instant>);
+
+/// Callbacks of `pagbar.after`
+#[derive(Default)]
+struct Timers(Vec<(Instant, RegistryKey)>);
+
+/// Which callbacks the script defines, known once its thread loaded it
+#[derive(Debug, Clone, Copy, Default)]
+struct Handlers {
+    on_click: bool,
+    on_action: bool,
+}
+
+pub struct LuaModule {
+    path: PathBuf,
+    /// Handed to the script on the first update, modules are
+    /// also created to validate the config
+    config: Option<toml::Table>,
+    handlers: Arc<Mutex<Handlers>>,
+    output: Arc<Mutex<Output>>,
+    events: Option<mpsc::Sender<Event>>,
+    /// Output the last markup error was reported for
+    failed: Option<String>,
+}
+
+impl LuaModule {
+    pub fn new(config: LuaConfig) -> Result<Self, String> {
+        let config_home = xdg::BaseDirectories::with_prefix("pagbar")
+            .ok()
+            .map(|dirs| dirs.get_config_home());
+        let path = super::user_path(&config.script, config_home);
+        // the script itself only runs on its thread
+        if !path.is_file() {
+            return Err(format!("{}: no such script", path.display()));
+        }
+
+        Ok(Self {
+            path,
+            config: Some(config.config),
+            handlers: Arc::default(),
+            output: Arc::default(),
+            events: None,
+            failed: None,
+        })
+    }
+}
+
+struct Script {
+    lua: Lua,
+    /// The table the script returned
+    module: RegistryKey,
+    interval: Duration,
+    /// Last error, reported once until it changes
+    error: Option<String>,
+}
+
+impl Script {
+    fn load(path: &Path, config: &toml::Table) -> mlua::Result<Self> {
+        let source = std::fs::read_to_string(path).map_err(mlua::Error::external)?;
+        let lua = Lua::new_with(
+            StdLib::STRING | StdLib::TABLE | StdLib::MATH | StdLib::UTF8 | StdLib::COROUTINE,
+            LuaOptions::default(),
+        )?;
+        lua.set_memory_limit(MEMORY_LIMIT)?;
+        lua.set_app_data(Deadline(None));
+        lua.set_app_data(Timers::default());
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(10_000),
+            |lua, _| match lua
+                .app_data_ref::<Deadline>()
+                .and_then(|deadline| deadline.0)
+            {
+                Some(deadline) if Instant::now() > deadline => Err(mlua::Error::runtime(format!(
+                    "timed out after {CALL_TIMEOUT:?}"
+                ))),
+                _ => Ok(()),
+            },
+        );
+        lua.globals().set("pagbar", helpers(&lua, config)?)?;
+
+        let module = with_deadline(&lua, || {
+            lua.load(&source)
+                .set_name(format!("@{}", path.display()))
+                .eval::<Table>()
+        })?;
+        let interval = module
+            .get::<_, Option<f64>>("interval")?
+            .unwrap_or(DEFAULT_INTERVAL);
+        // 0 would update in a loop
+        let interval = Duration::try_from_secs_f64(interval)
+            .ok()
+            .filter(|interval| !interval.is_zero())
+            .ok_or_else(|| mlua::Error::runtime(format!("invalid interval {interval}")))?;
+        let module = lua.create_registry_value(module)?;
+
+        Ok(Self {
+            lua,
+            module,
+            interval,
+            error: None,
+        })
+    }
+
+    fn module(&self) -> mlua::Result<Table<'_>> {
+        self.lua.registry_value(&self.module)
+    }
+
+    fn handlers(&self) -> mlua::Result<Handlers> {
+        let module = self.module()?;
+        let has = |name| {
+            module
+                .get::<_, Option<Function>>(name)
+                .is_ok_and(|function| function.is_some())
+        };
+        Ok(Handlers {
+            on_click: has("on_click"),
+            on_action: has("on_action"),
+        })
+    }
+
+    /// Call `name` on the module table if the script defines it
+    fn call<'lua, R: mlua::FromLuaMulti<'lua>>(
+        &'lua self,
+        name: &str,
+        args: impl mlua::IntoLuaMulti<'lua>,
+    ) -> mlua::Result<Option<R>> {
+        let Some(function) = self.module()?.get::<_, Option<Function>>(name)? else {
+            return Ok(None);
+        };
+        with_deadline(&self.lua, || function.call(args)).map(Some)
+    }
+
+    fn update(&self) -> mlua::Result<Output> {
+        let (text, level) = self
+            .call::<(Option<String>, Option<f32>)>("update", ())?
+            .unwrap_or_default();
+        let tooltip = self.call::<Option<String>>("tooltip", ())?.flatten();
+        Ok(Output {
+            text,
+            tooltip,
+            level,
+        })
+    }
+
+    fn handle(&self, event: Event) -> mlua::Result<()> {
+        match event {
+            Event::Action(action) if action == "click" => self.call::<()>("on_click", ())?,
+            Event::Action(action) => self.call::<()>("on_action", action)?,
+        };
+        Ok(())
+    }
+
+    /// Run the timers that are due, returns whether any did
+    fn run_timers(&self) -> mlua::Result<bool> {
+        let now = Instant::now();
+        let due = {
+            let mut timers = self.lua.app_data_mut::<Timers>().unwrap();
+            let (due, pending) = std::mem::take(&mut timers.0)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(at, _)| *at <= now);
+            timers.0 = pending;
+            due
+        };
+
+        let ran = !due.is_empty();
+        for (_, key) in due {
+            let callback = self.lua.registry_value::<Function>(&key)?;
+            self.lua.remove_registry_value(key)?;
+            with_deadline(&self.lua, || callback.call::<_, ()>(()))?;
+        }
+        Ok(ran)
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        let timers = self.lua.app_data_ref::<Timers>()?;
+        timers.0.iter().map(|(at, _)| *at).min()
+    }
+
+    fn report(&mut self, path: &Path, result: mlua::Result<()>) {
+        match result {
+            Ok(()) => self.error = None,
+            Err(err) => {
+                let err = err.to_string();
+                if self.error.as_ref() != Some(&err) {
+                    log::warn!("{}: {err}", path.display());
+                    self.error = Some(err);
+                }
+            }
+        }
+    }
+}
+
+fn with_deadline<R>(lua: &Lua, call: impl FnOnce() -> mlua::Result<R>) -> mlua::Result<R> {
+    lua.set_app_data(Deadline(Some(Instant::now() + CALL_TIMEOUT)));
+    let result = call();
+    lua.set_app_data(Deadline(None));
+    result
+}
+
+/// The `pagbar` table scripts use instead of `io` and `os`
+fn helpers<'lua>(lua: &'lua Lua, config: &toml::Table) -> mlua::Result<Table<'lua>> {
+    let pagbar = lua.create_table()?;
+    pagbar.set(
+        "config",
+        toml_to_lua(lua, &toml::Value::Table(config.clone()))?,
+    )?;
+
+    // output and exit code of a shell command
+    pagbar.set(
+        "exec",
+        lua.create_function(|_, command: String| {
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .stdin(std::process::Stdio::null())
+                .output()
+                .map_err(mlua::Error::external)?;
+            Ok((
+                String::from_utf8_lossy(&output.stdout).into_owned(),
+                output.status.code(),
+            ))
+        })?,
+    )?;
+    pagbar.set(
+        "spawn",
+        lua.create_function(|_, command: String| {
+            action::exec(&command);
+            Ok(())
+        })?,
+    )?;
+    pagbar.set(
+        "read",
+        lua.create_function(|_, path: String| Ok(std::fs::read_to_string(path).ok()))?,
+    )?;
+    // command for the ipc socket, returns the answer or nil and the error
+    pagbar.set(
+        "ipc",
+        lua.create_function(|_, command: String| {
+            Ok(match crate::ipc::send(&command) {
+                Ok(answer) => (Some(answer), None),
+                Err(err) => (None, Some(err)),
+            })
+        })?,
+    )?;
+    pagbar.set(
+        "after",
+        lua.create_function(|lua, (seconds, callback): (f64, Function)| {
+            let delay = Duration::try_from_secs_f64(seconds)
+                .map_err(|_| mlua::Error::runtime(format!("invalid delay {seconds}")))?;
+            let key = lua.create_registry_value(callback)?;
+            let mut timers = lua.app_data_mut::<Timers>().unwrap();
+            timers.0.push((Instant::now() + delay, key));
+            Ok(())
+        })?,
+    )?;
+    pagbar.set(
+        "time",
+        lua.create_function(|_, ()| {
+            Ok(std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0., |time| time.as_secs_f64()))
+        })?,
+    )?;
+    // local time formatted with strftime
+    pagbar.set(
+        "date",
+        lua.create_function(|_, format: String| {
+            let items = chrono::format::StrftimeItems::new(&format).collect::<Vec<_>>();
+            if items.contains(&chrono::format::Item::Error) {
+                return Err(mlua::Error::runtime(format!("invalid format \"{format}\"")));
+            }
+            Ok(chrono::Local::now()
+                .format_with_items(items.into_iter())
+                .to_string())
+        })?,
+    )?;
+    pagbar.set(
+        "log",
+        lua.create_function(|_, message: String| {
+            log::info!("{message}");
+            Ok(())
+        })?,
+    )?;
+    Ok(pagbar)
+}
+
+fn toml_to_lua<'lua>(lua: &'lua Lua, value: &toml::Value) -> mlua::Result<Value<'lua>> {
+    Ok(match value {
+        toml::Value::String(string) => Value::String(lua.create_string(string)?),
+        toml::Value::Integer(integer) => Value::Integer(*integer),
+        toml::Value::Float(float) => Value::Number(*float),
+        toml::Value::Boolean(boolean) => Value::Boolean(*boolean),
+        toml::Value::Datetime(datetime) => Value::String(lua.create_string(datetime.to_string())?),
+        toml::Value::Array(array) => Value::Table(
+            lua.create_sequence_from(
+                array
+                    .iter()
+                    .map(|value| toml_to_lua(lua, value))
+                    .collect::<mlua::Result<Vec<_>>>()?,
+            )?,
+        ),
+        toml::Value::Table(table) => {
+            let lua_table = lua.create_table()?;
+            for (key, value) in table {
+                lua_table.set(key.as_str(), toml_to_lua(lua, value)?)?;
+            }
+            Value::Table(lua_table)
+        }
+    })
+}
+
+/// Load the script, then update it every interval and whenever
+/// an event or timer ran
+fn run(
+    path: PathBuf,
+    config: toml::Table,
+    events: mpsc::Receiver<Event>,
+    handlers: Arc<Mutex<Handlers>>,
+    output: Arc<Mutex<Output>>,
+    ctx: egui::Context,
+) {
+    let loaded = Script::load(&path, &config).and_then(|script| Ok((script.handlers()?, script)));
+    let mut script = match loaded {
+        Ok((loaded, script)) => {
+            *handlers.lock().unwrap() = loaded;
+            script
+        }
+        Err(err) => {
+            log::warn!("{}: {err}", path.display());
+            return;
+        }
+    };
+
+    let mut next_update = Instant::now();
+    loop {
+        let now = Instant::now();
+        if now >= next_update {
+            match script.update() {
+                Ok(update) => {
+                    *output.lock().unwrap() = update;
+                    script.report(&path, Ok(()));
+                }
+                Err(err) => script.report(&path, Err(err)),
+            }
+            ctx.request_repaint();
+            next_update = now + script.interval;
+        }
+
+        let wake = script
+            .next_timer()
+            .map_or(next_update, |timer| timer.min(next_update));
+        match events.recv_timeout(wake.saturating_duration_since(Instant::now())) {
+            Ok(event) => {
+                let result = script.handle(event);
+                script.report(&path, result);
+                next_update = Instant::now();
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+
+        match script.run_timers() {
+            Ok(true) => next_update = Instant::now(),
+            Ok(false) => {}
+            Err(err) => script.report(&path, Err(err)),
+        }
+    }
+}
+
+impl Module for LuaModule {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        if let Some(config) = self.config.take() {
+            let (sender, events) = mpsc::channel();
+            let path = self.path.clone();
+            let handlers = self.handlers.clone();
+            let output = self.output.clone();
+            let ctx = ctx.clone();
+            std::thread::spawn(move || run(path, config, events, handlers, output, ctx));
+            self.events = Some(sender);
+        }
+
+        let Some(text) = self.output.lock().unwrap().text.clone() else {
+            return Content::default();
+        };
+        match markup::parse(&text, bar.text.into(), bar) {
+            Ok(content) => content,
+            Err(err) => {
+                if self.failed.as_ref() != Some(&text) {
+                    log::warn!("{}: invalid markup: {err}", self.path.display());
+                    self.failed = Some(text.clone());
+                }
+                Content::default().push(text, bar.text)
+            }
+        }
+    }
+
+    fn tooltip(&mut self, bar: &Bar) -> Option<Content> {
+        let tooltip = self.output.lock().unwrap().tooltip.clone()?;
+        Some(
+            markup::parse(&tooltip, bar.text.into(), bar)
+                .unwrap_or_else(|_| Content::default().push(tooltip, bar.text)),
+        )
+    }
+
+    fn click_action(&self) -> Option<&str> {
+        self.handlers.lock().unwrap().on_click.then_some("click")
+    }
+
+    fn level(&self) -> Option<f32> {
+        self.output.lock().unwrap().level
+    }
+
+    /// `click` runs `on_click`, anything else is passed to `on_action`
+    fn on_action(&mut self, action: &str) -> bool {
+        let handlers = *self.handlers.lock().unwrap();
+        let known = if action == "click" {
+            handlers.on_click
+        } else {
+            handlers.on_action
+        };
+        if let (true, Some(events)) = (known, &self.events) {
+            let _ = events.send(Event::Action(action.into()));
+        }
+        known
+    }
+}
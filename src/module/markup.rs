@@ -0,0 +1,392 @@
+//! Pango style markup for module text, e.g. `<b>3</b> <span color="#e06c75">failed</span>`
+//!
+//! Tags: `<b>`, `<i>`, `<u>`, `<s>`, `<tt>`, `<big>`, `<small>` and `<span>` with
+//! `foreground`/`color`, `background`, `size`, `weight`, `style`, `underline`,
+//! `strikethrough` and `font_family` attributes. Colors are hex or one of the
+//! bar's `text`, `secondary`, `warning`, `critical`. Bold text uses the `bold`
+//! family from `[fonts.families]`, egui has no synthetic bold.
+
+use egui::{Color32, FontFamily};
+
+use super::{Content, Size, Style};
+use crate::bar::{Bar, Color};
+
+/// Scale of `<big>`, `<small>`, `larger` and `smaller`, like pango's
+const SCALE_STEP: f32 = 1.2;
+
+#[derive(Clone)]
+struct State {
+    tag: String,
+    color: Color32,
+    style: Style,
+}
+
+/// Convert markup to content, text outside of tags is drawn in `color`
+pub fn parse(markup: &str, color: Color32, bar: &Bar) -> Result<Content, String> {
+    let mut content = Content::default();
+    let mut stack = vec![State {
+        tag: String::new(),
+        color,
+        style: Style::default(),
+    }];
+    let mut rest = markup;
+
+    while !rest.is_empty() {
+        let (text, tag) = match rest.find('<') {
+            Some(start) => (&rest[..start], Some(&rest[start..])),
+            None => (rest, None),
+        };
+        if !text.is_empty() {
+            let state = stack.last().unwrap();
+            content = content.styled(unescape(text)?, state.color, state.style.clone());
+        }
+
+        let Some(tag) = tag else {
+            break;
+        };
+        let end = tag
+            .find('>')
+            .ok_or_else(|| format!("unclosed tag \"{tag}\""))?;
+        let inner = &tag[1..end];
+        rest = &tag[end + 1..];
+
+        if let Some(name) = inner.strip_prefix('/') {
+            let name = name.trim();
+            let open = stack.pop().filter(|state| !state.tag.is_empty());
+            match open {
+                Some(state) if state.tag == name => {}
+                Some(state) => {
+                    return Err(format!("</{name}> closes <{}>", state.tag));
+                }
+                None => return Err(format!("</{name}> without an opening tag")),
+            }
+        } else {
+            let mut state = stack.last().unwrap().clone();
+            open_tag(inner, &mut state, bar)?;
+            stack.push(state);
+        }
+    }
+
+    match stack.pop() {
+        Some(state) if !state.tag.is_empty() => Err(format!("unclosed <{}>", state.tag)),
+        _ => Ok(content),
+    }
+}
+
+/// Apply the tag `inner` (without the angle brackets) to `state`
+fn open_tag(inner: &str, state: &mut State, bar: &Bar) -> Result<(), String> {
+    let (name, attributes) = inner
+        .trim()
+        .split_once(char::is_whitespace)
+        .unwrap_or((inner.trim(), ""));
+    state.tag = name.into();
+
+    match name {
+        "b" => bold(state, bar),
+        "i" => state.style.italics = true,
+        "u" => state.style.underline = true,
+        "s" => state.style.strikethrough = true,
+        "tt" => state.style.family = Some(FontFamily::Monospace),
+        "big" => state.style.size = scale(state.style.size, SCALE_STEP),
+        "small" => state.style.size = scale(state.style.size, 1. / SCALE_STEP),
+        "span" => {
+            for (key, value) in parse_attributes(attributes)? {
+                span_attribute(&key, &value, state, bar)?;
+            }
+        }
+        _ => return Err(format!("unknown tag <{name}>")),
+    }
+
+    if name != "span" && !attributes.trim().is_empty() {
+        return Err(format!("<{name}> takes no attributes"));
+    }
+    Ok(())
+}
+
+fn span_attribute(key: &str, value: &str, state: &mut State, bar: &Bar) -> Result<(), String> {
+    match key {
+        "foreground" | "fgcolor" | "color" => state.color = color(value, bar)?,
+        "background" | "bgcolor" => state.style.background = color(value, bar)?,
+        "size" | "font_size" => state.style.size = size(value, state.style.size)?,
+        "weight" | "font_weight" => match value {
+            "bold" | "ultrabold" | "heavy" | "semibold" => bold(state, bar),
+            "normal" | "light" | "ultralight" => state.style.family = None,
+            _ => return Err(format!("unknown weight \"{value}\"")),
+        },
+        "style" | "font_style" => state.style.italics = matches!(value, "italic" | "oblique"),
+        "underline" => state.style.underline = value != "none",
+        "strikethrough" => state.style.strikethrough = value == "true",
+        "font_family" | "face" | "font" => {
+            state.style.family = Some(match value {
+                "proportional" | "sans" | "sans-serif" => FontFamily::Proportional,
+                "monospace" | "mono" => FontFamily::Monospace,
+                name if has_family(bar, name) => FontFamily::Name(name.into()),
+                name => return Err(format!("font family \"{name}\" is not defined")),
+            })
+        }
+        _ => return Err(format!("unknown span attribute \"{key}\"")),
+    }
+    Ok(())
+}
+
+fn has_family(bar: &Bar, name: &str) -> bool {
+    bar.fonts
+        .families
+        .contains_key(&FontFamily::Name(name.into()))
+}
+
+fn bold(state: &mut State, bar: &Bar) {
+    if has_family(bar, "bold") {
+        state.style.family = Some(FontFamily::Name("bold".into()));
+    }
+}
+
+fn scale(size: Size, factor: f32) -> Size {
+    match size {
+        Size::Inherit => Size::Scale(factor),
+        Size::Scale(scale) => Size::Scale(scale * factor),
+        Size::Points(points) => Size::Points(points * factor),
+    }
+}
+
+/// Pango sizes: names, `larger`/`smaller`, `12pt`, `120%`
+/// or a bare number in 1024ths of a point
+fn size(value: &str, current: Size) -> Result<Size, String> {
+    let named = match value {
+        "xx-small" => Some(0.579),
+        "x-small" => Some(0.694),
+        "small" => Some(0.833),
+        "medium" => Some(1.),
+        "large" => Some(1.2),
+        "x-large" => Some(1.44),
+        "xx-large" => Some(1.728),
+        _ => None,
+    };
+    let invalid = || format!("invalid size \"{value}\"");
+
+    Ok(if let Some(scale) = named {
+        Size::Scale(scale)
+    } else if value == "larger" {
+        scale(current, SCALE_STEP)
+    } else if value == "smaller" {
+        scale(current, 1. / SCALE_STEP)
+    } else if let Some(points) = value.strip_suffix("pt") {
+        Size::Points(points.trim().parse().map_err(|_| invalid())?)
+    } else if let Some(percent) = value.strip_suffix('%') {
+        Size::Scale(percent.trim().parse::<f32>().map_err(|_| invalid())? / 100.)
+    } else {
+        Size::Points(value.parse::<f32>().map_err(|_| invalid())? / 1024.)
+    })
+}
+
+fn color(value: &str, bar: &Bar) -> Result<Color32, String> {
+    Ok(match value {
+        "text" => bar.text.into(),
+        "secondary" => bar.text_secondary.into(),
+        "warning" => bar.warning.into(),
+        "critical" => bar.critical.into(),
+        hex => hex.parse::<Color>()?.into(),
+    })
+}
+
+/// `key="value"` pairs, single quotes work too
+fn parse_attributes(mut attributes: &str) -> Result<Vec<(String, String)>, String> {
+    let mut parsed = Vec::new();
+    loop {
+        attributes = attributes.trim_start();
+        if attributes.is_empty() {
+            return Ok(parsed);
+        }
+
+        let (key, rest) = attributes
+            .split_once('=')
+            .ok_or_else(|| format!("attribute without a value in \"{attributes}\""))?;
+        let rest = rest.trim_start();
+        let quote = rest
+            .chars()
+            .next()
+            .filter(|quote| matches!(quote, '"' | '\''))
+            .ok_or_else(|| format!("unquoted value of \"{}\"", key.trim()))?;
+        let end = rest[1..]
+            .find(quote)
+            .ok_or_else(|| format!("unclosed value of \"{}\"", key.trim()))?;
+
+        parsed.push((key.trim().to_string(), unescape(&rest[1..end + 1])?));
+        attributes = &rest[end + 2..];
+    }
+}
+
+/// Replace `&amp;`, `&lt;`, `&gt;`, `&quot;`, `&apos;` and `&#NN;`
+fn unescape(text: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| format!("unterminated entity in \"{text}\""))?;
+        let entity = &rest[start + 1..start + end];
+
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("unknown entity \"&{entity};\""))?,
+        };
+        unescaped.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(markup: &str) -> Vec<(String, Color32, Style)> {
+        parse(markup, Color32::WHITE, &Bar::test())
+            .unwrap()
+            .spans
+            .into_iter()
+            .map(|span| (span.text, span.color, span.style))
+            .collect()
+    }
+
+    fn error(markup: &str) -> String {
+        parse(markup, Color32::WHITE, &Bar::test()).unwrap_err()
+    }
+
+    fn scale(size: Size) -> f32 {
+        match size {
+            Size::Scale(scale) => scale,
+            size => panic!("expected a scale, got {size:?}"),
+        }
+    }
+
+    #[test]
+    fn nested_tags() {
+        let italics = Style {
+            italics: true,
+            ..Default::default()
+        };
+        let underlined = Style {
+            underline: true,
+            ..italics.clone()
+        };
+        assert_eq!(
+            spans("a<i>b<u>c</u>d</i>e"),
+            [
+                ("a".into(), Color32::WHITE, Style::default()),
+                ("b".into(), Color32::WHITE, italics.clone()),
+                ("c".into(), Color32::WHITE, underlined),
+                ("d".into(), Color32::WHITE, italics),
+                ("e".into(), Color32::WHITE, Style::default()),
+            ]
+        );
+
+        let green = Color32::from_rgb(0, 0xff, 0);
+        let bar = Bar::test();
+        assert_eq!(
+            spans(r##"<span color="#00ff00">x<span background='warning'>y</span></span>"##),
+            [
+                ("x".into(), green, Style::default()),
+                (
+                    "y".into(),
+                    green,
+                    Style {
+                        background: bar.warning.into(),
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            unescape("&lt;b&gt; &amp; &#65;&#x42; &quot;&apos;").unwrap(),
+            "<b> & AB \"'"
+        );
+        assert_eq!(spans("&lt;i&gt;")[0].0, "<i>");
+        assert_eq!(
+            spans(r#"<span color="&#x23;ff0000">x</span>"#)[0].1,
+            Color32::from_rgb(0xff, 0, 0)
+        );
+        assert_eq!(error("a &nbsp; b"), "unknown entity \"&nbsp;\"");
+        assert_eq!(error("&#xd800;"), "unknown entity \"&#xd800;\"");
+        assert_eq!(error("a & b"), "unterminated entity in \"a & b\"");
+    }
+
+    #[test]
+    fn sizes() {
+        let sizes = spans(concat!(
+            r#"<span size="larger">a<span size="120%">b</span>"#,
+            r#"<span size="smaller">c</span></span>"#,
+            r#"<big><big>d</big></big><small>e</small>"#,
+            r#"<span size="x-small">f</span>"#,
+        ))
+        .into_iter()
+        .map(|(_, _, style)| scale(style.size))
+        .collect::<Vec<_>>();
+        let expected = [1.2, 1.2, 1., 1.44, 1. / 1.2, 0.694];
+        assert_eq!(sizes.len(), expected.len());
+        for (size, expected) in sizes.into_iter().zip(expected) {
+            assert!((size - expected).abs() < 1e-5, "{size} != {expected}");
+        }
+
+        assert_eq!(
+            spans(r#"<span size="12pt">a<big>b</big></span><span size="10240">c</span>"#)
+                .into_iter()
+                .map(|(_, _, style)| style.size)
+                .collect::<Vec<_>>(),
+            [
+                Size::Points(12.),
+                Size::Points(12. * 1.2),
+                Size::Points(10.)
+            ]
+        );
+        assert_eq!(
+            error(r#"<span size="huge">x</span>"#),
+            "invalid size \"huge\""
+        );
+        assert_eq!(error(r#"<span size="%">x</span>"#), "invalid size \"%\"");
+    }
+
+    #[test]
+    fn mismatched_tags() {
+        assert_eq!(error("<b>x</i>"), "</i> closes <b>");
+        assert_eq!(error("<i><u>x</i></u>"), "</i> closes <u>");
+        assert_eq!(error("x</b>"), "</b> without an opening tag");
+        assert_eq!(error("<b>x"), "unclosed <b>");
+        assert_eq!(error("<b x"), "unclosed tag \"<b x\"");
+    }
+
+    #[test]
+    fn invalid_tags() {
+        assert_eq!(error("<blink>x</blink>"), "unknown tag <blink>");
+        assert_eq!(error(r#"<b class="x">x</b>"#), "<b> takes no attributes");
+        assert_eq!(
+            error(r#"<span colour="red">x</span>"#),
+            "unknown span attribute \"colour\""
+        );
+        assert_eq!(
+            error("<span color=red>x</span>"),
+            "unquoted value of \"color\""
+        );
+        assert_eq!(
+            error(r#"<span font_family="fancy">x</span>"#),
+            "font family \"fancy\" is not defined"
+        );
+    }
+}
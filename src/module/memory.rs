@@ -0,0 +1,142 @@
+use serde::Deserialize;
+use systemstat::{Memory as Usage, Platform, Swap};
+
+use super::{
+    format::{Template, Units, Value},
+    Content, Module,
+};
+use crate::bar::Bar;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MemoryConfig {
+    /// Template with {used} {free} {total} {percent} and their swap-* versions
+    #[serde(default = "default_format")]
+    format: String,
+    /// Shown instead of `format` after `cycle-format`
+    #[serde(default = "default_format_alt")]
+    format_alt: String,
+    #[serde(default)]
+    units: Units,
+}
+
+fn default_format() -> String {
+    "ram {percent}%".into()
+}
+
+fn default_format_alt() -> String {
+    "ram {used}/{total}".into()
+}
+
+/// Placeholders of memory formats
+const PLACEHOLDERS: &[&str] = &[
+    "used",
+    "free",
+    "total",
+    "percent",
+    "swap-used",
+    "swap-free",
+    "swap-total",
+    "swap-percent",
+];
+
+pub struct Memory {
+    sys: systemstat::System,
+    format: Template,
+    format_alt: Template,
+    units: Units,
+    /// Show `format-alt`
+    alt: bool,
+    /// Used percentage
+    level: Option<f32>,
+}
+
+impl Memory {
+    pub fn new(config: MemoryConfig) -> Result<Self, String> {
+        Ok(Self {
+            sys: systemstat::System::new(),
+            format: Template::parse(&config.format, PLACEHOLDERS)?,
+            format_alt: Template::parse(&config.format_alt, PLACEHOLDERS)?,
+            units: config.units,
+            alt: false,
+            level: None,
+        })
+    }
+
+    fn value(&self, name: &str, memory: &Usage, swap: Option<&Swap>) -> Option<Value> {
+        let (total, free) = match name.strip_prefix("swap-") {
+            Some(_) => {
+                let swap = swap.filter(|swap| swap.total.as_u64() > 0)?;
+                (swap.total.as_u64(), swap.free.as_u64())
+            }
+            None => (memory.total.as_u64(), memory.free.as_u64()),
+        };
+        let used = total.saturating_sub(free);
+
+        Some(match name.trim_start_matches("swap-") {
+            "used" => Value::bytes(used, self.units),
+            "free" => Value::bytes(free, self.units),
+            "total" => Value::bytes(total, self.units),
+            "percent" => Value::number(used as f64 / total.max(1) as f64 * 100., 0),
+            _ => return None,
+        })
+    }
+}
+
+impl Module for Memory {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        ctx.request_repaint_after(super::POLL_INTERVAL);
+        let (memory, swap) = match self.sys.memory_and_swap() {
+            Ok((memory, swap)) => (Ok(memory), Some(swap)),
+            Err(_) => (self.sys.memory(), None),
+        };
+        let Ok(memory) = memory else {
+            self.level = None;
+            return Content::default();
+        };
+
+        let used = memory.total.as_u64().saturating_sub(memory.free.as_u64());
+        self.level = Some(used as f32 / memory.total.as_u64().max(1) as f32 * 100.);
+
+        let format = if self.alt {
+            &self.format_alt
+        } else {
+            &self.format
+        };
+        format.render(Content::default(), bar, bar.text_secondary.into(), |name| {
+            self.value(name, &memory, swap.as_ref())
+        })
+    }
+
+    fn tooltip(&mut self, bar: &Bar) -> Option<Content> {
+        let (memory, swap) = self.sys.memory_and_swap().ok()?;
+        let used = |total: systemstat::ByteSize, free: systemstat::ByteSize| {
+            format!(
+                "{} / {}",
+                self.units
+                    .format(total.as_u64().saturating_sub(free.as_u64())),
+                self.units.format(total.as_u64())
+            )
+        };
+
+        Some(
+            Content::default()
+                .push("memory ", bar.text)
+                .push(used(memory.total, memory.free), bar.text_secondary)
+                .push("\nswap ", bar.text)
+                .push(used(swap.total, swap.free), bar.text_secondary),
+        )
+    }
+
+    fn level(&self) -> Option<f32> {
+        self.level
+    }
+
+    fn on_action(&mut self, action: &str) -> bool {
+        match action {
+            "cycle-format" => self.alt = !self.alt,
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,468 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use super::{
+    format::{Template, Value},
+    Content, Module, States,
+};
+use crate::bar::Bar;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TemperatureConfig {
+    /// hwmon chip (`coretemp`, `k10temp/Tctl`), thermal zone
+    /// (`x86_pkg_temp`, `thermal_zone1`) or a file, `thermal_zone0` by default
+    sensor: Option<String>,
+    #[serde(default = "default_label")]
+    label: String,
+    /// Template with {label} {temp} {unit}
+    #[serde(default = "default_format")]
+    format: String,
+    /// Decimal places of {temp}
+    #[serde(default)]
+    precision: usize,
+    /// Also the unit of `states`
+    #[serde(default)]
+    unit: Unit,
+}
+
+fn default_label() -> String {
+    "cpu".into()
+}
+
+fn default_format() -> String {
+    "[{label} ][{temp}{unit}]".into()
+}
+
+impl Default for TemperatureConfig {
+    fn default() -> Self {
+        Self {
+            sensor: None,
+            label: default_label(),
+            format: default_format(),
+            precision: 0,
+            unit: Unit::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl Unit {
+    fn convert(self, celsius: f32) -> f32 {
+        match self {
+            Unit::Celsius => celsius,
+            Unit::Fahrenheit => celsius * 9. / 5. + 32.,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+        }
+    }
+}
+
+/// Files a temperature is read from, both in millidegrees celsius
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Source {
+    input: PathBuf,
+    critical: Option<PathBuf>,
+}
+
+pub struct Temperature {
+    sensor: String,
+    label: String,
+    format: Template,
+    precision: usize,
+    unit: Unit,
+    sysfs_root: PathBuf,
+    /// Resolved on first use, hwmon numbering isn't stable across boots
+    source: Option<Source>,
+    /// Last reading in the configured unit
+    level: Option<f32>,
+}
+
+impl Temperature {
+    pub fn new(config: TemperatureConfig) -> Result<Self, String> {
+        Self::with_sysfs_root(config, "/sys".into())
+    }
+
+    /// Read sensors from sysfs mounted at `sysfs_root`
+    fn with_sysfs_root(config: TemperatureConfig, sysfs_root: PathBuf) -> Result<Self, String> {
+        Ok(Self {
+            sensor: config.sensor.unwrap_or("thermal_zone0".into()),
+            label: config.label,
+            format: Template::parse(&config.format, &["label", "temp", "unit"])?,
+            precision: config.precision,
+            unit: config.unit,
+            sysfs_root,
+            source: None,
+            level: None,
+        })
+    }
+
+    /// Find the files of the configured sensor
+    fn resolve(&self) -> Option<Source> {
+        if Path::new(&self.sensor).is_absolute() {
+            return Some(Source {
+                input: self.sensor.clone().into(),
+                critical: None,
+            });
+        }
+
+        let (chip, label) = match self.sensor.split_once('/') {
+            Some((chip, label)) => (chip, Some(label)),
+            None => (self.sensor.as_str(), None),
+        };
+        if label.is_none() {
+            if let Some(source) = self.thermal_zone(chip) {
+                return Some(source);
+            }
+        }
+        self.hwmon(chip, label)
+    }
+
+    /// Zone by directory name or by its `type`
+    fn thermal_zone(&self, name: &str) -> Option<Source> {
+        let zones = std::fs::read_dir(self.sysfs_root.join("class/thermal")).ok()?;
+        let mut zones = zones
+            .flatten()
+            .map(|zone| zone.path())
+            .filter(|zone| zone.join("temp").exists())
+            .collect::<Vec<_>>();
+        zones.sort();
+
+        let zone = zones.into_iter().find(|zone| {
+            zone.file_name().is_some_and(|file| file == name)
+                || read(&zone.join("type")).as_deref() == Some(name)
+        })?;
+
+        // trip points are numbered, the critical one is marked by its type
+        let critical = (0..16)
+            .find(|i| {
+                read(&zone.join(format!("trip_point_{i}_type"))).as_deref() == Some("critical")
+            })
+            .map(|i| zone.join(format!("trip_point_{i}_temp")));
+
+        Some(Source {
+            input: zone.join("temp"),
+            critical,
+        })
+    }
+
+    /// Input of a hwmon chip by its `name`, the first one unless
+    /// a `label` is given
+    fn hwmon(&self, chip: &str, label: Option<&str>) -> Option<Source> {
+        let chips = std::fs::read_dir(self.sysfs_root.join("class/hwmon")).ok()?;
+        let mut chips = chips.flatten().map(|chip| chip.path()).collect::<Vec<_>>();
+        chips.sort();
+
+        chips
+            .into_iter()
+            .filter(|path| read(&path.join("name")).as_deref() == Some(chip))
+            .flat_map(|path| inputs(&path))
+            .find(|(path, sensor)| {
+                label.is_none_or(|label| {
+                    read(&path.join(format!("temp{sensor}_label"))).as_deref() == Some(label)
+                })
+            })
+            .map(|(path, sensor)| Source {
+                input: path.join(format!("temp{sensor}_input")),
+                critical: Some(path.join(format!("temp{sensor}_crit"))),
+            })
+    }
+
+    /// Every hwmon temperature sensor as `(chip label, celsius)`
+    fn sensors(&self) -> Vec<(String, f32)> {
+        let Ok(chips) = std::fs::read_dir(self.sysfs_root.join("class/hwmon")) else {
+            return Vec::new();
+        };
+
+        let mut sensors = Vec::new();
+        for chip in chips.flatten().map(|chip| chip.path()) {
+            let name = read(&chip.join("name")).unwrap_or_default();
+            for (path, sensor) in inputs(&chip) {
+                let Some(celsius) = millidegrees(&path.join(format!("temp{sensor}_input"))) else {
+                    continue;
+                };
+                let label = read(&path.join(format!("temp{sensor}_label")))
+                    .unwrap_or(format!("temp{sensor}"));
+                sensors.push((format!("{name} {label}"), celsius));
+            }
+        }
+
+        sensors.sort_by(|a, b| a.0.cmp(&b.0));
+        sensors
+    }
+}
+
+fn read(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+fn millidegrees(path: &Path) -> Option<f32> {
+    read(path)?.parse::<f32>().ok().map(|millis| millis / 1000.)
+}
+
+/// `temp<n>_input` files of a hwmon chip, by number
+fn inputs(chip: &Path) -> Vec<(PathBuf, u32)> {
+    let Ok(entries) = std::fs::read_dir(chip) else {
+        return Vec::new();
+    };
+
+    let mut inputs = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file = entry.file_name();
+            let sensor = file
+                .to_str()?
+                .strip_prefix("temp")?
+                .strip_suffix("_input")?
+                .parse()
+                .ok()?;
+            Some((chip.to_path_buf(), sensor))
+        })
+        .collect::<Vec<_>>();
+    inputs.sort_by_key(|(_, sensor)| *sensor);
+    inputs
+}
+
+impl Module for Temperature {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        ctx.request_repaint_after(super::POLL_INTERVAL);
+        if self.source.is_none() {
+            self.source = self.resolve();
+        }
+        let celsius = self
+            .source
+            .as_ref()
+            .and_then(|source| millidegrees(&source.input));
+
+        self.level = celsius.map(|celsius| self.unit.convert(celsius));
+
+        self.format.render(
+            Content::default(),
+            bar,
+            bar.text_secondary.into(),
+            |name| match name {
+                "label" => Some(Value::Text(self.label.clone())).filter(|_| !self.label.is_empty()),
+                "temp" => Some(Value::number(self.level?, self.precision)),
+                "unit" => Some(Value::Text(self.unit.symbol().into())),
+                _ => None,
+            },
+        )
+    }
+
+    fn level(&self) -> Option<f32> {
+        self.level
+    }
+
+    /// Critical at the sensor's own critical point
+    fn default_states(&self) -> States {
+        let critical = self
+            .source
+            .as_ref()
+            .and_then(|source| source.critical.as_ref())
+            .and_then(|path| millidegrees(path))
+            .map(|celsius| self.unit.convert(celsius));
+        States {
+            warning: None,
+            critical,
+        }
+    }
+
+    fn tooltip(&mut self, bar: &Bar) -> Option<Content> {
+        let sensors = self.sensors();
+        if sensors.is_empty() {
+            return None;
+        }
+
+        let mut content = Content::default();
+        for (i, (label, celsius)) in sensors.into_iter().enumerate() {
+            let separator = if i == 0 { "" } else { "\n" };
+            content = content.push(format!("{separator}{label} "), bar.text).push(
+                format!("{:.1}{}", self.unit.convert(celsius), self.unit.symbol()),
+                bar.text_secondary,
+            );
+        }
+        Some(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fake sysfs tree, removed when dropped
+    struct Sysfs(PathBuf);
+
+    impl Sysfs {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("pagbar-temperature-{}-{name}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            Self(root)
+        }
+
+        fn file(&self, path: &str, contents: &str) -> &Self {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, format!("{contents}\n")).unwrap();
+            self
+        }
+
+        fn sensor(&self, sensor: &str, unit: Unit) -> Temperature {
+            let config = TemperatureConfig {
+                sensor: Some(sensor.into()),
+                unit,
+                ..Default::default()
+            };
+            let mut temperature = Temperature::with_sysfs_root(config, self.0.clone()).unwrap();
+            temperature.source = temperature.resolve();
+            temperature
+        }
+    }
+
+    impl Drop for Sysfs {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn thermal_zones(name: &str) -> Sysfs {
+        let sysfs = Sysfs::new(name);
+        sysfs
+            .file("class/thermal/thermal_zone0/temp", "45000")
+            .file("class/thermal/thermal_zone0/type", "acpitz")
+            .file("class/thermal/thermal_zone1/temp", "61500")
+            .file("class/thermal/thermal_zone1/type", "x86_pkg_temp")
+            .file("class/thermal/thermal_zone1/trip_point_0_type", "passive")
+            .file("class/thermal/thermal_zone1/trip_point_0_temp", "90000")
+            .file("class/thermal/thermal_zone1/trip_point_1_type", "critical")
+            .file("class/thermal/thermal_zone1/trip_point_1_temp", "100000")
+            // cooling devices live next to zones but have no temperature
+            .file("class/thermal/cooling_device0/type", "Processor");
+        sysfs
+    }
+
+    fn hwmon(name: &str) -> Sysfs {
+        let sysfs = Sysfs::new(name);
+        sysfs
+            .file("class/hwmon/hwmon0/name", "acpitz")
+            .file("class/hwmon/hwmon0/temp1_input", "27800")
+            .file("class/hwmon/hwmon1/name", "k10temp")
+            .file("class/hwmon/hwmon1/temp1_input", "52250")
+            .file("class/hwmon/hwmon1/temp1_label", "Tctl")
+            .file("class/hwmon/hwmon1/temp3_input", "48000")
+            .file("class/hwmon/hwmon1/temp3_label", "Tccd1")
+            .file("class/hwmon/hwmon1/temp3_crit", "95000");
+        sysfs
+    }
+
+    fn input(temperature: &Temperature) -> Option<f32> {
+        millidegrees(&temperature.source.as_ref()?.input)
+    }
+
+    #[test]
+    fn thermal_zone_by_directory_and_type() {
+        let sysfs = thermal_zones("zones");
+
+        assert_eq!(
+            input(&sysfs.sensor("thermal_zone0", Unit::Celsius)),
+            Some(45.)
+        );
+        assert_eq!(
+            input(&sysfs.sensor("x86_pkg_temp", Unit::Celsius)),
+            Some(61.5)
+        );
+        assert_eq!(input(&sysfs.sensor("acpitz", Unit::Celsius)), Some(45.));
+        assert!(sysfs
+            .sensor("cooling_device0", Unit::Celsius)
+            .source
+            .is_none());
+    }
+
+    #[test]
+    fn thermal_zone_critical_trip_point() {
+        let sysfs = thermal_zones("trip-points");
+
+        let zone = sysfs.sensor("x86_pkg_temp", Unit::Celsius);
+        assert_eq!(zone.default_states().critical, Some(100.));
+        assert_eq!(zone.default_states().warning, None);
+        let zone = sysfs.sensor("x86_pkg_temp", Unit::Fahrenheit);
+        assert_eq!(zone.default_states().critical, Some(212.));
+        // no critical trip point
+        let zone = sysfs.sensor("thermal_zone0", Unit::Celsius);
+        assert_eq!(zone.default_states().critical, None);
+    }
+
+    #[test]
+    fn hwmon_by_name_and_label() {
+        let sysfs = hwmon("hwmon");
+
+        // the first input without a label
+        assert_eq!(input(&sysfs.sensor("k10temp", Unit::Celsius)), Some(52.25));
+        assert_eq!(
+            input(&sysfs.sensor("k10temp/Tctl", Unit::Celsius)),
+            Some(52.25)
+        );
+        assert_eq!(
+            input(&sysfs.sensor("k10temp/Tccd1", Unit::Celsius)),
+            Some(48.)
+        );
+        // without a label thermal zones are tried first, then chips
+        assert_eq!(input(&sysfs.sensor("acpitz", Unit::Celsius)), Some(27.8));
+        assert!(sysfs
+            .sensor("k10temp/Tccd2", Unit::Celsius)
+            .source
+            .is_none());
+        assert!(sysfs.sensor("coretemp", Unit::Celsius).source.is_none());
+    }
+
+    #[test]
+    fn hwmon_critical() {
+        let sysfs = hwmon("hwmon-critical");
+
+        let sensor = sysfs.sensor("k10temp/Tccd1", Unit::Celsius);
+        assert_eq!(sensor.default_states().critical, Some(95.));
+        // temp1_crit doesn't exist
+        let sensor = sysfs.sensor("k10temp/Tctl", Unit::Celsius);
+        assert_eq!(sensor.default_states().critical, None);
+    }
+
+    #[test]
+    fn absolute_sensor_path() {
+        let sysfs = hwmon("absolute");
+
+        let path = sysfs.0.join("class/hwmon/hwmon1/temp3_input");
+        let sensor = sysfs.sensor(path.to_str().unwrap(), Unit::Celsius);
+        assert_eq!(input(&sensor), Some(48.));
+        assert_eq!(sensor.default_states().critical, None);
+    }
+
+    #[test]
+    fn every_hwmon_sensor_in_tooltip() {
+        let sysfs = hwmon("sensors");
+
+        let sensors = sysfs.sensor("k10temp", Unit::Celsius).sensors();
+        assert_eq!(
+            sensors,
+            [
+                ("acpitz temp1".to_string(), 27.8),
+                ("k10temp Tccd1".to_string(), 48.),
+                ("k10temp Tctl".to_string(), 52.25),
+            ]
+        );
+    }
+}
@@ -0,0 +1,584 @@
+//! WebAssembly plugin modules, run by wasmi on their own thread
+//!
+//! A plugin exports `memory`, `pagbar_update()` and optionally
+//! `pagbar_init()`, `pagbar_click()`, `pagbar_action()` and `pagbar_timer(id)`.
+//! It imports from `pagbar`:
+//!
+//! - `input_len() -> i32`, `input_read(ptr)`: the config as TOML during
+//!   `pagbar_init`, the action name during `pagbar_action`
+//! - `set_text(ptr, len)`, `set_tooltip(ptr, len)`: markup, an empty tooltip hides it
+//! - `set_level(f32)`: level for `states`, NaN clears it
+//! - `set_interval(ms)`, `set_timer(id, ms)`: at least 10ms apart
+//! - `log(ptr, len)`
+//!
+//! Of WASI only stdout/stderr (logged), clocks, random, args/environ (empty)
+//! and `proc_exit` work, everything else fails with `ENOSYS`. A plugin that
+//! traps, runs out of fuel or exits is stopped and its slot left blank.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    ops::Range,
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Deserialize;
+use wasmi::{
+    core::ValType, Caller, Config, Engine, Error, Extern, ExternType, Instance, InstancePre,
+    Linker, Memory, Store, StoreLimits, StoreLimitsBuilder, Val,
+};
+
+use super::{markup, Content, Module};
+use crate::bar::Bar;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WasmConfig {
+    /// Relative to `$XDG_DATA_HOME/pagbar/plugins`
+    plugin: PathBuf,
+    /// Handed to the plugin as TOML during `pagbar_init`
+    #[serde(default)]
+    config: toml::Table,
+}
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+const MIN_INTERVAL: Duration = Duration::from_millis(10);
+/// Roughly one per instruction, every call into the plugin gets this much
+const FUEL: u64 = 100_000_000;
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+const WASI: &str = "wasi_snapshot_preview1";
+/// WASI functions that do something, the rest fail with ENOSYS
+const WASI_IMPLEMENTED: &[&str] = &[
+    "fd_write",
+    "clock_time_get",
+    "clock_res_get",
+    "random_get",
+    "args_sizes_get",
+    "args_get",
+    "environ_sizes_get",
+    "environ_get",
+    "sched_yield",
+    "proc_exit",
+];
+const ERRNO_SUCCESS: i32 = 0;
+const ERRNO_BADF: i32 = 8;
+const ERRNO_INVAL: i32 = 28;
+const ERRNO_NOSYS: i32 = 52;
+
+/// What the plugin's thread hands to the bar
+#[derive(Debug, Default, Clone)]
+struct Output {
+    text: Option<String>,
+    tooltip: Option<String>,
+    level: Option<f32>,
+}
+
+/// State of the store, changed by the plugin through its imports
+struct Host {
+    limits: StoreLimits,
+    /// Bytes `input_read` copies
+    input: Vec<u8>,
+    output: Output,
+    interval: Duration,
+    timers: Vec<(Instant, i32)>,
+    /// Partial line written to stdout or stderr
+    stdout: Vec<u8>,
+    name: String,
+    started: Instant,
+}
+
+pub struct WasmModule {
+    path: PathBuf,
+    /// Moved to its thread on the first update, modules are
+    /// also created to validate the config
+    plugin: Option<Linked>,
+    on_click: bool,
+    on_action: bool,
+    output: Arc<Mutex<Output>>,
+    events: Option<mpsc::Sender<String>>,
+    /// Output the last markup error was reported for
+    failed: Option<String>,
+}
+
+impl WasmModule {
+    pub fn new(config: WasmConfig) -> Result<Self, String> {
+        let plugins = xdg::BaseDirectories::with_prefix("pagbar")
+            .ok()
+            .map(|dirs| dirs.get_data_home().join("plugins"));
+        let path = super::user_path(&config.plugin, plugins);
+        let plugin = Linked::load(&path, &config.config)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+
+        Ok(Self {
+            path,
+            on_click: plugin.on_click,
+            on_action: plugin.on_action,
+            plugin: Some(plugin),
+            output: Arc::default(),
+            events: None,
+            failed: None,
+        })
+    }
+}
+
+/// Compiled and linked, but no plugin code ran yet
+struct Linked {
+    store: Store<Host>,
+    pre: InstancePre,
+    on_click: bool,
+    on_action: bool,
+}
+
+impl Linked {
+    /// Compile and link, the `start` function and `pagbar_init` run on
+    /// the plugin's thread
+    fn load(path: &std::path::Path, config: &toml::Table) -> Result<Self, String> {
+        let wasm = std::fs::read(path).map_err(|err| err.to_string())?;
+        let mut engine_config = Config::default();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config);
+        let module = wasmi::Module::new(&engine, &wasm).map_err(|err| err.to_string())?;
+
+        let host = Host {
+            limits: StoreLimitsBuilder::new()
+                .memory_size(MEMORY_LIMIT)
+                .instances(1)
+                .build(),
+            input: toml::to_string(config)
+                .map_err(|err| err.to_string())?
+                .into_bytes(),
+            output: Output::default(),
+            interval: DEFAULT_INTERVAL,
+            timers: Vec::new(),
+            stdout: Vec::new(),
+            name: path.display().to_string(),
+            started: Instant::now(),
+        };
+        let mut store = Store::new(&engine, host);
+        store.limiter(|host| &mut host.limits);
+
+        let mut linker = Linker::new(&engine);
+        link_pagbar(&mut linker).map_err(|err| err.to_string())?;
+        link_wasi(&mut linker, &module).map_err(|err| err.to_string())?;
+        let pre = linker
+            .instantiate(&mut store, &module)
+            .map_err(|err| err.to_string())?;
+
+        let exports = |name| module.get_export(name);
+        if !matches!(exports("memory"), Some(ExternType::Memory(_))) {
+            return Err("plugin doesn't export its memory".into());
+        }
+        if !matches!(exports("pagbar_update"), Some(ExternType::Func(_))) {
+            return Err("plugin doesn't export pagbar_update".into());
+        }
+        Ok(Self {
+            on_click: matches!(exports("pagbar_click"), Some(ExternType::Func(_))),
+            on_action: matches!(exports("pagbar_action"), Some(ExternType::Func(_))),
+            store,
+            pre,
+        })
+    }
+
+    /// Run the `start` function and initialize the plugin
+    fn start(mut self) -> Result<Plugin, Error> {
+        self.store.set_fuel(FUEL)?;
+        let instance = self.pre.start(&mut self.store)?;
+        let mut plugin = Plugin {
+            store: self.store,
+            instance,
+        };
+        plugin.init()?;
+        Ok(plugin)
+    }
+}
+
+struct Plugin {
+    store: Store<Host>,
+    instance: Instance,
+}
+
+impl Plugin {
+    /// Call an export if the plugin has it, every call gets fresh fuel
+    fn call(&mut self, name: &str, args: &[Val]) -> Result<(), Error> {
+        let Some(func) = self.instance.get_func(&self.store, name) else {
+            return Ok(());
+        };
+        self.store.set_fuel(FUEL)?;
+        func.call(&mut self.store, args, &mut [])
+    }
+
+    fn init(&mut self) -> Result<(), Error> {
+        // WASI reactors initialize their runtime here
+        self.call("_initialize", &[])?;
+        self.call("pagbar_init", &[])?;
+        self.store.data_mut().input.clear();
+        Ok(())
+    }
+
+    fn action(&mut self, action: String) -> Result<(), Error> {
+        if action == "click" {
+            return self.call("pagbar_click", &[]);
+        }
+        self.store.data_mut().input = action.into_bytes();
+        let result = self.call("pagbar_action", &[]);
+        self.store.data_mut().input.clear();
+        result
+    }
+
+    /// Run the timers that are due, returns whether any did
+    fn run_timers(&mut self) -> Result<bool, Error> {
+        let now = Instant::now();
+        let (due, pending) = std::mem::take(&mut self.store.data_mut().timers)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(at, _)| *at <= now);
+        self.store.data_mut().timers = pending;
+
+        for (_, id) in &due {
+            self.call("pagbar_timer", &[Val::I32(*id)])?;
+        }
+        Ok(!due.is_empty())
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        self.store.data().timers.iter().map(|(at, _)| *at).min()
+    }
+}
+
+fn memory(caller: &Caller<'_, Host>) -> Result<Memory, Error> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| Error::new("plugin doesn't export its memory"))
+}
+
+/// Where `len` bytes at `ptr` are in the plugin's memory, checked before
+/// anything is allocated for them
+fn bounds(caller: &Caller<'_, Host>, ptr: i32, len: i32) -> Result<Range<usize>, Error> {
+    let size = memory(caller)?.data(caller).len();
+    let (Ok(start), Ok(len)) = (usize::try_from(ptr), usize::try_from(len)) else {
+        return Err(Error::new("negative pointer or length"));
+    };
+    match start.checked_add(len) {
+        Some(end) if end <= size => Ok(start..end),
+        _ => Err(Error::new("out of bounds memory access")),
+    }
+}
+
+fn read(caller: &Caller<'_, Host>, ptr: i32, len: i32) -> Result<Vec<u8>, Error> {
+    let range = bounds(caller, ptr, len)?;
+    Ok(memory(caller)?.data(caller)[range].to_vec())
+}
+
+fn read_string(caller: &Caller<'_, Host>, ptr: i32, len: i32) -> Result<String, Error> {
+    Ok(String::from_utf8_lossy(&read(caller, ptr, len)?).into_owned())
+}
+
+fn write(caller: &mut Caller<'_, Host>, ptr: i32, bytes: &[u8]) -> Result<(), Error> {
+    memory(caller)?
+        .write(caller, ptr as u32 as usize, bytes)
+        .map_err(|err| Error::new(err.to_string()))
+}
+
+/// Clamped so a plugin asking for 0 doesn't spin its thread
+fn millis(ms: i32) -> Duration {
+    Duration::from_millis(ms.max(0) as u64).max(MIN_INTERVAL)
+}
+
+/// The `pagbar` imports
+fn link_pagbar(linker: &mut Linker<Host>) -> Result<(), wasmi::errors::LinkerError> {
+    linker.func_wrap("pagbar", "input_len", |caller: Caller<'_, Host>| {
+        caller.data().input.len() as i32
+    })?;
+    linker.func_wrap(
+        "pagbar",
+        "input_read",
+        |mut caller: Caller<'_, Host>, ptr: i32| {
+            let input = caller.data().input.clone();
+            write(&mut caller, ptr, &input)
+        },
+    )?;
+    linker.func_wrap(
+        "pagbar",
+        "set_text",
+        |mut caller: Caller<'_, Host>, ptr: i32, len: i32| {
+            let text = read_string(&caller, ptr, len)?;
+            caller.data_mut().output.text = Some(text);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "pagbar",
+        "set_tooltip",
+        |mut caller: Caller<'_, Host>, ptr: i32, len: i32| {
+            let tooltip = read_string(&caller, ptr, len)?;
+            caller.data_mut().output.tooltip = Some(tooltip).filter(|tooltip| !tooltip.is_empty());
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "pagbar",
+        "set_level",
+        |mut caller: Caller<'_, Host>, level: f32| {
+            caller.data_mut().output.level = Some(level).filter(|level| !level.is_nan());
+        },
+    )?;
+    linker.func_wrap(
+        "pagbar",
+        "set_interval",
+        |mut caller: Caller<'_, Host>, ms: i32| {
+            caller.data_mut().interval = millis(ms);
+        },
+    )?;
+    linker.func_wrap(
+        "pagbar",
+        "set_timer",
+        |mut caller: Caller<'_, Host>, id: i32, ms: i32| {
+            let at = Instant::now() + millis(ms);
+            caller.data_mut().timers.push((at, id));
+        },
+    )?;
+    linker.func_wrap(
+        "pagbar",
+        "log",
+        |caller: Caller<'_, Host>, ptr: i32, len: i32| {
+            let message = read_string(&caller, ptr, len)?;
+            log::info!("{}: {message}", caller.data().name);
+            Ok(())
+        },
+    )?;
+    Ok(())
+}
+
+/// The part of WASI preview 1 plugins get, no files or sockets
+fn link_wasi(linker: &mut Linker<Host>, module: &wasmi::Module) -> Result<(), Error> {
+    linker.func_wrap(
+        WASI,
+        "fd_write",
+        |mut caller: Caller<'_, Host>, fd: i32, iovs: i32, iovs_len: i32, written: i32| {
+            if fd != 1 && fd != 2 {
+                return Ok(ERRNO_BADF);
+            }
+            let iovs_len = iovs_len
+                .checked_mul(8)
+                .ok_or_else(|| Error::new("out of bounds memory access"))?;
+            let iovs = read(&caller, iovs, iovs_len)?;
+            let mut total = 0u32;
+            for iov in iovs.chunks_exact(8) {
+                let ptr = i32::from_le_bytes(iov[..4].try_into().unwrap());
+                let len = i32::from_le_bytes(iov[4..].try_into().unwrap());
+                let bytes = read(&caller, ptr, len)?;
+                total = total.saturating_add(bytes.len() as u32);
+                caller.data_mut().stdout.extend(bytes);
+            }
+            let host = caller.data_mut();
+            while let Some(end) = host.stdout.iter().position(|&byte| byte == b'\n') {
+                let line = host.stdout.drain(..=end).collect::<Vec<_>>();
+                log::info!(
+                    "{}: {}",
+                    host.name,
+                    String::from_utf8_lossy(&line).trim_end()
+                );
+            }
+            write(&mut caller, written, &total.to_le_bytes())?;
+            Ok(ERRNO_SUCCESS)
+        },
+    )?;
+    linker.func_wrap(
+        WASI,
+        "clock_time_get",
+        |mut caller: Caller<'_, Host>, clock: i32, _precision: i64, time: i32| {
+            let nanos = match clock {
+                // realtime
+                0 => SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default(),
+                // monotonic, process and thread cputime
+                1..=3 => caller.data().started.elapsed(),
+                _ => return Ok(ERRNO_INVAL),
+            }
+            .as_nanos() as u64;
+            write(&mut caller, time, &nanos.to_le_bytes())?;
+            Ok(ERRNO_SUCCESS)
+        },
+    )?;
+    linker.func_wrap(
+        WASI,
+        "clock_res_get",
+        |mut caller: Caller<'_, Host>, _clock: i32, resolution: i32| {
+            write(&mut caller, resolution, &1000u64.to_le_bytes())?;
+            Ok(ERRNO_SUCCESS)
+        },
+    )?;
+    linker.func_wrap(
+        WASI,
+        "random_get",
+        |mut caller: Caller<'_, Host>, ptr: i32, len: i32| {
+            let len = bounds(&caller, ptr, len)?.len();
+            let mut bytes = Vec::with_capacity(len);
+            while bytes.len() < len {
+                let mut hasher = RandomState::new().build_hasher();
+                hasher.write_usize(bytes.len());
+                bytes.extend(hasher.finish().to_le_bytes());
+            }
+            bytes.truncate(len);
+            write(&mut caller, ptr, &bytes)?;
+            Ok(ERRNO_SUCCESS)
+        },
+    )?;
+    for (sizes, get) in [
+        ("args_sizes_get", "args_get"),
+        ("environ_sizes_get", "environ_get"),
+    ] {
+        linker.func_wrap(
+            WASI,
+            sizes,
+            |mut caller: Caller<'_, Host>, count: i32, size: i32| {
+                write(&mut caller, count, &0u32.to_le_bytes())?;
+                write(&mut caller, size, &0u32.to_le_bytes())?;
+                Ok(ERRNO_SUCCESS)
+            },
+        )?;
+        linker.func_wrap(WASI, get, |_: Caller<'_, Host>, _: i32, _: i32| {
+            ERRNO_SUCCESS
+        })?;
+    }
+    linker.func_wrap(WASI, "sched_yield", |_: Caller<'_, Host>| ERRNO_SUCCESS)?;
+    linker.func_wrap(WASI, "proc_exit", |_: Caller<'_, Host>, code: i32| {
+        Err::<(), _>(Error::i32_exit(code))
+    })?;
+
+    for import in module.imports() {
+        if import.module() != WASI || WASI_IMPLEMENTED.contains(&import.name()) {
+            continue;
+        }
+        let ExternType::Func(ty) = import.ty() else {
+            continue;
+        };
+        let errno = ty.results() == [ValType::I32];
+        linker.func_new(
+            WASI,
+            import.name(),
+            ty.clone(),
+            move |_, _, results| match errno {
+                true => {
+                    results[0] = Val::I32(ERRNO_NOSYS);
+                    Ok(())
+                }
+                false => Err(Error::new("unsupported WASI call")),
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Update the plugin every interval and whenever an action or timer ran,
+/// the thread ends when the plugin fails or the module is dropped
+fn run(
+    plugin: Linked,
+    path: PathBuf,
+    events: mpsc::Receiver<String>,
+    output: Arc<Mutex<Output>>,
+    ctx: egui::Context,
+) {
+    let fail = |err: Error| {
+        log::warn!("{}: stopped: {err}", path.display());
+        *output.lock().unwrap() = Output::default();
+        ctx.request_repaint();
+    };
+    let mut plugin = match plugin.start() {
+        Ok(plugin) => plugin,
+        Err(err) => return fail(err),
+    };
+
+    let mut next_update = Instant::now();
+    loop {
+        let now = Instant::now();
+        if now >= next_update {
+            if let Err(err) = plugin.call("pagbar_update", &[]) {
+                return fail(err);
+            }
+            *output.lock().unwrap() = plugin.store.data().output.clone();
+            ctx.request_repaint();
+            next_update = now + plugin.store.data().interval;
+        }
+
+        let wake = plugin
+            .next_timer()
+            .map_or(next_update, |timer| timer.min(next_update));
+        match events.recv_timeout(wake.saturating_duration_since(Instant::now())) {
+            Ok(action) => {
+                if let Err(err) = plugin.action(action) {
+                    return fail(err);
+                }
+                next_update = Instant::now();
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+
+        match plugin.run_timers() {
+            Ok(true) => next_update = Instant::now(),
+            Ok(false) => {}
+            Err(err) => return fail(err),
+        }
+    }
+}
+
+impl Module for WasmModule {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        if let Some(plugin) = self.plugin.take() {
+            let (sender, events) = mpsc::channel();
+            let path = self.path.clone();
+            let output = self.output.clone();
+            let ctx = ctx.clone();
+            std::thread::spawn(move || run(plugin, path, events, output, ctx));
+            self.events = Some(sender);
+        }
+
+        let Some(text) = self.output.lock().unwrap().text.clone() else {
+            return Content::default();
+        };
+        match markup::parse(&text, bar.text.into(), bar) {
+            Ok(content) => content,
+            Err(err) => {
+                if self.failed.as_ref() != Some(&text) {
+                    log::warn!("{}: invalid markup: {err}", self.path.display());
+                    self.failed = Some(text.clone());
+                }
+                Content::default().push(text, bar.text)
+            }
+        }
+    }
+
+    fn tooltip(&mut self, bar: &Bar) -> Option<Content> {
+        let tooltip = self.output.lock().unwrap().tooltip.clone()?;
+        Some(
+            markup::parse(&tooltip, bar.text.into(), bar)
+                .unwrap_or_else(|_| Content::default().push(tooltip, bar.text)),
+        )
+    }
+
+    fn click_action(&self) -> Option<&str> {
+        self.on_click.then_some("click")
+    }
+
+    fn level(&self) -> Option<f32> {
+        self.output.lock().unwrap().level
+    }
+
+    /// `click` calls `pagbar_click`, anything else `pagbar_action`
+    fn on_action(&mut self, action: &str) -> bool {
+        let known = if action == "click" {
+            self.on_click
+        } else {
+            self.on_action
+        };
+        if let (true, Some(events)) = (known, &self.events) {
+            let _ = events.send(action.into());
+        }
+        known
+    }
+}
@@ -0,0 +1,434 @@
+//! Native plugins, shared libraries in `$XDG_DATA_HOME/pagbar/plugins`
+//! that add module kinds and layouts through a C ABI
+//!
+//! A plugin exports `pagbar_plugin`, returning a [`PluginInfo`] that lives
+//! as long as the library. Its `abi_version` is checked before anything else
+//! is read, the structs below only change together with [`ABI_VERSION`].
+//! Strings are nul terminated UTF-8, the ones a plugin returns stay valid
+//! until its next call with the same instance.
+
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_void, CStr, CString},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use crate::{
+    bar::Bar,
+    layout::{self, Layout},
+    module::{markup, Content, Module, Panel, Slot, SlotStatus},
+    Position,
+};
+
+/// Version of the structs below, plugins built against another one are refused
+pub const ABI_VERSION: u32 = 1;
+
+/// Symbol every plugin exports
+const ENTRY: &[u8] = b"pagbar_plugin\0";
+
+/// Signature of `pagbar_plugin`
+pub type PluginEntry = unsafe extern "C" fn() -> *const PluginInfo;
+
+#[repr(C)]
+pub struct PluginInfo {
+    /// The [`ABI_VERSION`] the plugin was built against
+    pub abi_version: u32,
+    pub name: *const c_char,
+    pub modules: *const ModuleVTable,
+    pub module_count: usize,
+    pub layouts: *const LayoutVTable,
+    pub layout_count: usize,
+}
+
+/// A module kind, used with `kind = "<kind>"`
+#[repr(C)]
+pub struct ModuleVTable {
+    pub kind: *const c_char,
+    /// Create an instance from the module's options as TOML, on failure
+    /// returns null and writes a message of at most `error_len` bytes to `error`
+    pub create: unsafe extern "C" fn(
+        options: *const c_char,
+        error: *mut c_char,
+        error_len: usize,
+    ) -> *mut c_void,
+    pub destroy: unsafe extern "C" fn(instance: *mut c_void),
+    /// Markup to show, called every time the bar is drawn. `repaint_ms`
+    /// can be set to redraw the bar after that many milliseconds
+    pub update: unsafe extern "C" fn(instance: *mut c_void, repaint_ms: *mut u32) -> *const c_char,
+    /// Markup of the tooltip, or null for none
+    pub tooltip: Option<unsafe extern "C" fn(instance: *mut c_void) -> *const c_char>,
+    /// Left click, unless `on-click-left` is set
+    pub click: Option<unsafe extern "C" fn(instance: *mut c_void)>,
+    /// Returns false for actions the module doesn't know
+    pub action: Option<unsafe extern "C" fn(instance: *mut c_void, action: *const c_char) -> bool>,
+    /// Level compared against `states`, NaN for none
+    pub level: Option<unsafe extern "C" fn(instance: *mut c_void) -> f32>,
+}
+
+/// A layout, used with `layout = "<name>"` in `[bar.<name>]`
+#[repr(C)]
+pub struct LayoutVTable {
+    pub name: *const c_char,
+    /// Write a rectangle for each of the `count` slots to `rects`, in points
+    /// from the top left of a `width` by `height` bar. Slot sizes are measured
+    /// in the previous frame, zero before the first one
+    pub arrange: unsafe extern "C" fn(
+        slots: *const SlotInfo,
+        count: usize,
+        width: f32,
+        height: f32,
+        position: u32,
+        rects: *mut Rect,
+    ),
+}
+
+#[repr(C)]
+pub struct SlotInfo {
+    pub name: *const c_char,
+    /// 0 first, 1 center, 2 last
+    pub panel: u32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// A loaded plugin, libraries are never unloaded
+struct Plugin {
+    name: String,
+    info: &'static PluginInfo,
+    _library: libloading::Library,
+}
+
+// SAFETY: the info is immutable and the vtables only hold function pointers
+unsafe impl Send for Plugin {}
+unsafe impl Sync for Plugin {}
+
+struct Registry {
+    modules: HashMap<String, &'static ModuleVTable>,
+    layouts: HashMap<String, &'static LayoutVTable>,
+    _plugins: Vec<Plugin>,
+}
+
+// SAFETY: see `Plugin`
+unsafe impl Send for Registry {}
+unsafe impl Sync for Registry {}
+
+fn plugin_dir() -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix("pagbar")
+        .ok()
+        .map(|dirs| dirs.get_data_home().join("plugins"))
+}
+
+/// Plugins are loaded on first use
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut registry = Registry {
+            modules: HashMap::new(),
+            layouts: HashMap::new(),
+            _plugins: Vec::new(),
+        };
+        let Some(entries) = plugin_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+            return registry;
+        };
+        let mut paths = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "so"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let plugin = match load(&path) {
+                Ok(plugin) => plugin,
+                Err(err) => {
+                    log::error!("plugin {}: {err}", path.display());
+                    continue;
+                }
+            };
+            let info = plugin.info;
+            for module in slice(info.modules, info.module_count) {
+                let kind = string(module.kind);
+                if registry.modules.insert(kind.clone(), module).is_some() {
+                    log::warn!("plugin \"{}\" replaces module kind \"{kind}\"", plugin.name);
+                }
+            }
+            for layout in slice(info.layouts, info.layout_count) {
+                let name = string(layout.name);
+                if registry.layouts.insert(name.clone(), layout).is_some() {
+                    log::warn!("plugin \"{}\" replaces layout \"{name}\"", plugin.name);
+                }
+            }
+            log::info!("loaded plugin \"{}\" from {}", plugin.name, path.display());
+            registry._plugins.push(plugin);
+        }
+        registry
+    })
+}
+
+fn load(path: &Path) -> Result<Plugin, String> {
+    // SAFETY: running the library's initializers is the point of loading it
+    let library = unsafe { libloading::Library::new(path) }.map_err(|err| {
+        // dlerror already names the file
+        let prefix = format!("{}: ", path.display());
+        err.to_string().replace(&prefix, "")
+    })?;
+    // SAFETY: `pagbar_plugin` has the `PluginEntry` signature by contract,
+    // what it returns isn't read before the ABI version is checked
+    let info = unsafe {
+        let entry = library
+            .get::<PluginEntry>(ENTRY)
+            .map_err(|_| "doesn't export pagbar_plugin, is it a pagbar plugin?".to_string())?;
+        entry()
+    };
+    // SAFETY: the plugin promises the info outlives the library, which is never unloaded
+    let info = unsafe { info.as_ref() }.ok_or("pagbar_plugin returned null")?;
+
+    if info.abi_version != ABI_VERSION {
+        return Err(format!(
+            "built for plugin ABI version {}, this pagbar supports version {ABI_VERSION}, \
+             rebuild it against this version",
+            info.abi_version
+        ));
+    }
+    Ok(Plugin {
+        name: string(info.name),
+        info,
+        _library: library,
+    })
+}
+
+fn slice<T>(ptr: *const T, len: usize) -> &'static [T] {
+    if ptr.is_null() || len == 0 {
+        return &[];
+    }
+    // SAFETY: plugins hand out arrays of `len` entries that live as long as the library
+    unsafe { std::slice::from_raw_parts(ptr, len) }
+}
+
+/// Copy a string from a plugin, lossily
+fn string(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    // SAFETY: plugins pass nul terminated strings
+    unsafe { CStr::from_ptr(ptr) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Module kinds added by plugins
+pub fn kinds() -> Vec<String> {
+    let mut kinds = registry().modules.keys().cloned().collect::<Vec<_>>();
+    kinds.sort();
+    kinds
+}
+
+pub fn has_layout(name: &str) -> bool {
+    registry().layouts.contains_key(name)
+}
+
+/// Instantiate a module kind of a plugin, None if no plugin has it
+pub fn create_module(kind: &str, options: &toml::Table) -> Option<Result<Box<dyn Module>, String>> {
+    let vtable = *registry().modules.get(kind)?;
+    Some(NativeModule::new(vtable, options).map(|module| Box::new(module) as Box<dyn Module>))
+}
+
+pub fn create_layout(name: &str, slots: Vec<Slot>) -> Option<Box<dyn Layout>> {
+    let vtable = *registry().layouts.get(name)?;
+    Some(Box::new(NativeLayout {
+        vtable,
+        sizes: vec![egui::Vec2::ZERO; slots.len()],
+        slots,
+    }))
+}
+
+/// Instance of a module kind from a plugin
+struct NativeModule {
+    vtable: &'static ModuleVTable,
+    instance: *mut c_void,
+}
+
+impl NativeModule {
+    fn new(vtable: &'static ModuleVTable, options: &toml::Table) -> Result<Self, String> {
+        let options = toml::to_string(options).map_err(|err| err.to_string())?;
+        let options = CString::new(options).map_err(|err| err.to_string())?;
+        let mut error = [0 as c_char; 512];
+
+        // SAFETY: the buffer is as long as the plugin is told
+        let instance =
+            unsafe { (vtable.create)(options.as_ptr(), error.as_mut_ptr(), error.len()) };
+        if instance.is_null() {
+            // the plugin may fill the whole buffer without a nul
+            error[error.len() - 1] = 0;
+            let error = string(error.as_ptr());
+            return Err(if error.is_empty() {
+                format!("plugin module \"{}\" failed to start", string(vtable.kind))
+            } else {
+                error
+            });
+        }
+        Ok(Self { vtable, instance })
+    }
+}
+
+impl Drop for NativeModule {
+    fn drop(&mut self) {
+        // SAFETY: the instance came from this vtable's `create`
+        unsafe { (self.vtable.destroy)(self.instance) }
+    }
+}
+
+fn parse_markup(text: String, bar: &Bar) -> Content {
+    markup::parse(&text, bar.text.into(), bar).unwrap_or_else(|err| {
+        log::warn!("plugin markup \"{text}\": {err}");
+        Content::default().push(text, bar.text)
+    })
+}
+
+impl Module for NativeModule {
+    fn update(&mut self, ctx: &egui::Context, bar: &Bar) -> Content {
+        let mut repaint_ms = 0;
+        // SAFETY: the vtable matches ABI_VERSION, which was checked on load,
+        // and the instance lives until drop. The string stays valid until the
+        // next call on the instance and is copied right away
+        let text = string(unsafe { (self.vtable.update)(self.instance, &mut repaint_ms) });
+        if repaint_ms > 0 {
+            ctx.request_repaint_after(Duration::from_millis(repaint_ms as u64));
+        }
+        parse_markup(text, bar)
+    }
+
+    fn tooltip(&mut self, bar: &Bar) -> Option<Content> {
+        // SAFETY: the vtable matches ABI_VERSION, which was checked on load,
+        // and the instance lives until drop. The string stays valid until the
+        // next call on the instance and is copied right away
+        let tooltip = unsafe { (self.vtable.tooltip?)(self.instance) };
+        (!tooltip.is_null()).then(|| parse_markup(string(tooltip), bar))
+    }
+
+    fn click_action(&self) -> Option<&str> {
+        self.vtable.click.map(|_| "click")
+    }
+
+    fn level(&self) -> Option<f32> {
+        // SAFETY: the vtable matches ABI_VERSION and the instance lives until drop
+        let level = unsafe { (self.vtable.level?)(self.instance) };
+        (!level.is_nan()).then_some(level)
+    }
+
+    fn on_action(&mut self, action: &str) -> bool {
+        if let (Some(click), "click") = (self.vtable.click, action) {
+            // SAFETY: the vtable matches ABI_VERSION and the instance lives until drop
+            unsafe { click(self.instance) };
+            return true;
+        }
+        let (Some(on_action), Ok(action)) = (self.vtable.action, CString::new(action)) else {
+            return false;
+        };
+        // SAFETY: as for `click`, and `action` is a nul terminated string
+        // that outlives the call
+        unsafe { on_action(self.instance, action.as_ptr()) }
+    }
+}
+
+/// Layout of a plugin, slots are drawn where the plugin places them
+struct NativeLayout {
+    vtable: &'static LayoutVTable,
+    slots: Vec<Slot>,
+    /// Measured in the last frame
+    sizes: Vec<egui::Vec2>,
+}
+
+impl Layout for NativeLayout {
+    fn display(&mut self, ctx: &egui::Context, bar: &Bar) {
+        let visuals: egui::Visuals = bar.into();
+        ctx.set_visuals(visuals);
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let area = ui.max_rect();
+            let names = self
+                .slots
+                .iter()
+                .map(|slot| CString::new(slot.config.name.as_str()).unwrap_or_default())
+                .collect::<Vec<_>>();
+            let infos = self
+                .slots
+                .iter()
+                .zip(&names)
+                .zip(&self.sizes)
+                .map(|((slot, name), size)| SlotInfo {
+                    name: name.as_ptr(),
+                    panel: match slot.config.panel {
+                        Panel::First => 0,
+                        Panel::Center => 1,
+                        Panel::Last => 2,
+                    },
+                    width: size.x,
+                    height: size.y,
+                })
+                .collect::<Vec<_>>();
+            let position = match bar.position {
+                Position::Top => 0,
+                Position::Bottom => 1,
+                Position::Left => 2,
+                Position::Right => 3,
+            };
+
+            let mut rects = vec![Rect::default(); infos.len()];
+            // SAFETY: both arrays hold `count` entries
+            unsafe {
+                (self.vtable.arrange)(
+                    infos.as_ptr(),
+                    infos.len(),
+                    area.width(),
+                    area.height(),
+                    position,
+                    rects.as_mut_ptr(),
+                )
+            };
+
+            for ((slot, rect), size) in self.slots.iter_mut().zip(rects).zip(&mut self.sizes) {
+                let rect = egui::Rect::from_min_size(
+                    area.min + egui::vec2(rect.x, rect.y),
+                    egui::vec2(rect.width, rect.height),
+                );
+                let mut child = ui.child_ui(rect, egui::Layout::left_to_right(egui::Align::Center));
+                child.style_mut().wrap = Some(false);
+                let measured = slot.show(ctx, &mut child, bar).rect.size();
+                if measured != *size {
+                    *size = measured;
+                    ctx.request_repaint();
+                }
+            }
+        });
+    }
+
+    fn action(&mut self, module: &str, action: &str) -> Result<(), String> {
+        layout::slots_action(self.slots.iter_mut(), module, action)
+    }
+
+    fn popup(&mut self, ui: &mut egui::Ui, bar: &Bar, module: &str) {
+        layout::slot_popup(self.slots.iter_mut(), ui, bar, module);
+    }
+
+    fn sample(&mut self, ctx: &egui::Context, bar: &Bar) {
+        for slot in &mut self.slots {
+            slot.sample(ctx, bar);
+        }
+    }
+
+    fn status(&mut self) -> Vec<SlotStatus> {
+        self.slots.iter().map(Slot::status).collect()
+    }
+}
@@ -0,0 +1,72 @@
+//! Requests for content that is drawn outside of the bar window,
+//! modules leave them in the bar's context and the backend picks
+//! them up after each frame
+
+use crate::module::Content;
+
+/// Extended information for the hovered module
+#[derive(Debug, Clone)]
+pub struct Tooltip {
+    /// Rect of the module within the bar, in points
+    pub anchor: egui::Rect,
+    pub font: egui::FontId,
+    pub content: Content,
+}
+
+fn tooltip_id() -> egui::Id {
+    egui::Id::new("pagbar-tooltip")
+}
+
+pub fn request_tooltip(ctx: &egui::Context, tooltip: Tooltip) {
+    ctx.data_mut(|data| data.insert_temp(tooltip_id(), tooltip));
+}
+
+/// The tooltip requested during the last frame, if any
+pub fn take_tooltip(ctx: &egui::Context) -> Option<Tooltip> {
+    ctx.data_mut(|data| {
+        let tooltip = data.get_temp::<Tooltip>(tooltip_id());
+        data.remove::<Tooltip>(tooltip_id());
+        tooltip
+    })
+}
+
+/// Interactive popup of a module, open until the module is toggled again
+#[derive(Debug, Clone)]
+pub struct Menu {
+    pub module: String,
+    /// Rect of the module within the bar, in points
+    pub anchor: egui::Rect,
+}
+
+fn menu_id() -> egui::Id {
+    egui::Id::new("pagbar-menu")
+}
+
+fn menu_owner_id() -> egui::Id {
+    egui::Id::new("pagbar-menu-owner")
+}
+
+pub fn request_menu(ctx: &egui::Context, menu: Menu) {
+    ctx.data_mut(|data| data.insert_temp(menu_id(), menu));
+}
+
+/// The menu requested during the last frame, if any
+pub fn take_menu(ctx: &egui::Context) -> Option<Menu> {
+    ctx.data_mut(|data| {
+        let menu = data.get_temp::<Menu>(menu_id());
+        data.remove::<Menu>(menu_id());
+        menu
+    })
+}
+
+/// Make `module` the one whose menu is shown, a bar has a single menu
+pub fn claim_menu(ctx: &egui::Context, module: &str) {
+    ctx.data_mut(|data| data.insert_temp(menu_owner_id(), module.to_string()));
+}
+
+/// False once another module claimed the menu
+pub fn owns_menu(ctx: &egui::Context, module: &str) -> bool {
+    ctx.data(|data| data.get_temp::<String>(menu_owner_id()))
+        .as_deref()
+        == Some(module)
+}